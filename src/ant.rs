@@ -1,22 +1,38 @@
 use crate::{
+    colony::{Colony, ColonyId},
+    food::FoodSource,
     gui::SimStatistics,
-    pheromone::Pheromones,
-    utils::{calc_rotation_angle, get_rand_unit_vec2},
+    map::WallGrid,
+    pheromone::{ChannelId, Pheromones},
+    rng::{EntityRng, SimRng},
+    sim::{on_sim_interval, SimSet, SimTick, SimUpdate},
+    steering::{
+        get_avoidance_force, get_steering_force, SteeringInput, SteeringStrategies,
+        MIDPOINT_STEERING,
+    },
+    utils::{calc_rotation_angle, get_rand_normal, get_rand_unit_vec2},
     *,
 };
 use bevy::{
+    ecs::query::WorldQuery,
     math::{vec2, vec3},
     prelude::*,
     time::common_conditions::on_timer,
+    utils::StableHashMap,
 };
-use rand::{thread_rng, Rng};
-use std::{f32::consts::PI, time::Duration};
+use rand::Rng;
+use std::{cell::RefCell, f32::consts::PI, time::Duration};
+use thread_local::ThreadLocal;
 
 pub struct AntPlugin;
 
+#[derive(Clone, Copy)]
 pub enum AntTask {
     FindFood,
+    /// Carrying food back to the nest
     FindHome,
+    /// Hungry, heading back to eat from the colony store
+    ReturnToEat,
 }
 
 #[derive(Component)]
@@ -24,11 +40,52 @@ pub struct Ant;
 #[derive(Component)]
 pub struct CurrentTask(pub AntTask);
 #[derive(Component)]
+pub struct AntCaste(pub Caste);
+#[derive(Component)]
 struct Velocity(Vec2);
 #[derive(Component)]
 struct Acceleration(Vec2);
 #[derive(Component)]
 struct PhStrength(f32);
+/// Estimated displacement from the nest, summed from every step of the ant.
+/// Only updated with `path_integration.enabled`, which can't change while running
+#[derive(Component, Default)]
+pub struct HomeVector(pub Vec2);
+/// Drains over time, the ant starves when it reaches zero
+#[derive(Component)]
+pub struct Energy(pub f32);
+/// Straight flight of an ant exploring with `Exploration::Levy`,
+/// a new one starts once the remaining distance runs out
+#[derive(Component, Default)]
+pub struct LevyFlight {
+    heading: Vec2,
+    remaining: f32,
+}
+
+/// Ant steered by `periodic_direction_update`
+#[derive(WorldQuery)]
+#[world_query(mutable)]
+struct SteeredAnt {
+    acceleration: &'static mut Acceleration,
+    rng: &'static mut EntityRng,
+    flight: &'static mut LevyFlight,
+    transform: &'static Transform,
+    current_task: &'static CurrentTask,
+    colony_id: &'static ColonyId,
+    caste: &'static AntCaste,
+    velocity: &'static Velocity,
+    home_vector: &'static HomeVector,
+}
+
+/// Pheromone dropped by an ant, collected by the parallel
+/// `drop_pheromone` and emitted into the grids afterwards
+struct PhDeposit {
+    ant: Entity,
+    colony_id: ColonyId,
+    task: AntTask,
+    pos: (i32, i32),
+    strength: f32,
+}
 
 #[derive(Resource)]
 struct AntScanRadius(f32);
@@ -37,89 +94,249 @@ pub struct AntFollowCameraPos(pub Vec2);
 
 impl Plugin for AntPlugin {
     fn build(&self, app: &mut App) {
+        app.init_resource::<SimConfig>();
+        let config = app.world.resource::<SimConfig>().ants.clone();
+
+        app.init_resource::<SteeringStrategies>();
+        app.world.resource_mut::<SteeringStrategies>().add_builtin();
+
         app.add_systems(Startup, setup)
-            .insert_resource(AntScanRadius(INITIAL_ANT_PH_SCAN_RADIUS))
+            .init_resource::<SimStatistics>()
+            .init_resource::<SimRng>()
+            .init_resource::<WallGrid>()
+            .insert_resource(AntScanRadius(config.initial_ph_scan_radius))
             .insert_resource(AntFollowCameraPos(Vec2::ZERO))
+            // Chained so that rng draws and ant updates always happen in the same order
             .add_systems(
-                Update,
-                drop_pheromone.run_if(on_timer(Duration::from_secs_f32(ANT_PH_DROP_INTERVAL))),
-            )
-            .add_systems(
-                Update,
-                check_wall_collision.run_if(on_timer(Duration::from_secs_f32(0.1))),
-            )
-            .add_systems(
-                Update,
-                check_home_food_collisions.run_if(on_timer(Duration::from_secs_f32(0.1))),
+                SimUpdate,
+                (
+                    check_home_food_collisions.run_if(on_sim_interval(|_| 0.1)),
+                    (
+                        clear_steer_cache,
+                        periodic_direction_update,
+                        channel_steering,
+                    )
+                        .chain()
+                        .run_if(on_sim_interval(|c| c.ants.direction_update_interval)),
+                    check_wall_collision.run_if(on_sim_interval(|_| 0.1)),
+                    update_position,
+                    integrate_path.run_if(|config: Res<SimConfig>| config.path_integration.enabled),
+                    drop_pheromone.run_if(on_sim_interval(|c| c.ants.ph_drop_interval)),
+                    decay_ph_strength
+                        .run_if(on_sim_interval(|c| c.ants.ph_strength_decay_interval)),
+                    update_scan_radius.run_if(on_sim_interval(|_| 1.0)),
+                    drain_energy
+                        .run_if(|config: Res<SimConfig>| config.energy.enabled)
+                        .run_if(on_sim_interval(|_| 0.1)),
+                )
+                    .chain()
+                    .in_set(SimSet::Ants),
             )
             .add_systems(Update, update_camera_follow_pos)
             .add_systems(
                 Update,
-                periodic_direction_update.run_if(on_timer(Duration::from_secs_f32(
-                    ANT_DIRECTION_UPDATE_INTERVAL,
-                ))),
+                (check_steering_strategy, clamp_scan_radius)
+                    .run_if(resource_changed::<SimConfig>()),
             )
             .add_systems(
                 Update,
                 update_stats.run_if(on_timer(Duration::from_secs_f32(3.0))),
             )
+            // Sprites, skipped when running headless
             .add_systems(
                 Update,
-                update_scan_radius.run_if(on_timer(Duration::from_secs_f32(1.0))),
-            )
-            .add_systems(
-                Update,
-                decay_ph_strength.run_if(on_timer(Duration::from_secs_f32(
-                    ANT_PH_STRENGTH_DECAY_INTERVAL,
-                ))),
-            )
-            .add_systems(Update, update_position.after(check_wall_collision));
+                (add_ant_sprites, update_ant_sprites).run_if(resource_exists::<AssetServer>()),
+            );
+    }
+}
+
+fn setup(mut commands: Commands, mut rng: ResMut<SimRng>, config: Res<SimConfig>) {
+    for (idx, colony) in config.colonies.iter().enumerate() {
+        let (x, y) = colony.home_location;
+        let mut counts = StableHashMap::default();
+        for _ in 0..colony.num_ants {
+            let caste = colony.castes.next_caste(&counts);
+            *counts.entry(caste).or_default() += 1;
+            spawn_ant(
+                &mut commands,
+                vec2(x, y),
+                ColonyId(idx),
+                caste,
+                &mut rng,
+                &config,
+            );
+        }
     }
 }
 
-fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
-    for _ in 0..NUM_ANTS {
-        commands.spawn((
-            SpriteBundle {
-                texture: asset_server.load(SPRITE_ANT),
-                sprite: Sprite {
-                    color: Color::rgb(1.1, 1.1, 1.0),
-                    ..default()
-                },
-                transform: Transform::from_xyz(HOME_LOCATION.0, HOME_LOCATION.1, ANT_Z_INDEX)
-                    .with_scale(Vec3::splat(ANT_SPRITE_SCALE)),
-                ..Default::default()
+/// Spawns an ant of the colony at `home`, heading out in a random direction
+pub fn spawn_ant(
+    commands: &mut Commands,
+    home: Vec2,
+    colony_id: ColonyId,
+    caste: Caste,
+    rng: &mut SimRng,
+    config: &SimConfig,
+) {
+    let ph_strength_scale = config.castes.get(caste).ph_strength_scale;
+    commands.spawn((
+        Transform::from_xyz(home.x, home.y, ANT_Z_INDEX),
+        Ant,
+        colony_id,
+        AntCaste(caste),
+        CurrentTask(AntTask::FindFood),
+        Velocity(get_rand_unit_vec2(&mut rng.rng)),
+        Acceleration(Vec2::ZERO),
+        PhStrength(config.ants.initial_ph_strength * ph_strength_scale),
+        Energy(config.energy.max_energy),
+        HomeVector::default(),
+        LevyFlight::default(),
+        rng.fork(),
+    ));
+}
+
+impl CasteRatios {
+    /// Caste of the next ant, the one furthest below its share of `counts`
+    pub fn next_caste(&self, counts: &StableHashMap<Caste, usize>) -> Caste {
+        let total_ratio: f32 = Caste::ALL.iter().map(|c| self.get(*c)).sum();
+        if total_ratio <= 0.0 {
+            return Caste::Forager;
+        }
+
+        let num_ants = counts.values().sum::<usize>() + 1;
+        let deficit = |caste: Caste| {
+            let count = counts.get(&caste).copied().unwrap_or(0);
+            self.get(caste) / total_ratio * num_ants as f32 - count as f32
+        };
+        Caste::ALL
+            .into_iter()
+            .reduce(|best, c| if deficit(c) > deficit(best) { c } else { best })
+            .unwrap_or(Caste::Forager)
+    }
+}
+
+fn check_steering_strategy(strategies: Res<SteeringStrategies>, config: Res<SimConfig>) {
+    let name = &config.ants.steering;
+    if !strategies.contains(name) {
+        warn!("no steering strategy named `{name}`, using `{MIDPOINT_STEERING}`");
+    }
+}
+
+fn add_ant_sprites(
+    mut commands: Commands,
+    ant_query: Query<(Entity, &Transform, &ColonyId), Added<Ant>>,
+    colony_query: Query<&Colony>,
+    asset_server: Res<AssetServer>,
+) {
+    for (entity, transform, colony_id) in ant_query.iter() {
+        let Some(colony) = colony_query.iter().find(|c| c.id == *colony_id) else {
+            continue;
+        };
+
+        commands.entity(entity).insert(SpriteBundle {
+            texture: asset_server.load(SPRITE_ANT),
+            sprite: Sprite {
+                color: colony.ant_color(Color::rgb(1.1, 1.1, 1.0)),
+                ..default()
             },
-            Ant,
-            CurrentTask(AntTask::FindFood),
-            Velocity(get_rand_unit_vec2()),
-            Acceleration(Vec2::ZERO),
-            PhStrength(ANT_INITIAL_PH_STRENGTH),
-        ));
+            transform: transform.with_scale(Vec3::splat(ANT_SPRITE_SCALE)),
+            ..Default::default()
+        });
     }
 }
 
-fn drop_pheromone(
-    mut ant_query: Query<(&Transform, &CurrentTask, &PhStrength), With<Ant>>,
-    mut pheromones: ResMut<Pheromones>,
+fn update_ant_sprites(
+    mut ant_query: Query<
+        (&CurrentTask, &ColonyId, &mut Sprite, &mut Handle<Image>),
+        Changed<CurrentTask>,
+    >,
+    colony_query: Query<&Colony>,
+    asset_server: Res<AssetServer>,
 ) {
-    for (transform, ant_task, ph_strength) in ant_query.iter_mut() {
-        let x = transform.translation.x as i32;
-        let y = transform.translation.y as i32;
+    for (ant_task, colony_id, mut sprite, mut image_handle) in ant_query.iter_mut() {
+        let Some(colony) = colony_query.iter().find(|c| c.id == *colony_id) else {
+            continue;
+        };
 
         match ant_task.0 {
-            AntTask::FindFood => pheromones.to_home.emit_signal(&(x, y), ph_strength.0),
-            AntTask::FindHome => pheromones.to_food.emit_signal(&(x, y), ph_strength.0),
+            AntTask::FindFood => {
+                *image_handle = asset_server.load(SPRITE_ANT);
+                sprite.color = colony.ant_color(Color::rgb(1.0, 1.0, 2.5));
+            }
+            AntTask::FindHome => {
+                *image_handle = asset_server.load(SPRITE_ANT_WITH_FOOD);
+                sprite.color = colony.ant_color(Color::rgb(1.0, 2.0, 1.0));
+            }
+            AntTask::ReturnToEat => {
+                *image_handle = asset_server.load(SPRITE_ANT);
+                sprite.color = colony.ant_color(Color::rgb(2.5, 1.0, 1.0));
+            }
         }
     }
 }
 
-fn update_scan_radius(mut scan_radius: ResMut<AntScanRadius>) {
-    if scan_radius.0 > INITIAL_ANT_PH_SCAN_RADIUS * ANT_PH_SCAN_RADIUS_SCALE {
+fn drop_pheromone(
+    ant_query: Query<(Entity, &Transform, &CurrentTask, &ColonyId, &PhStrength), With<Ant>>,
+    mut pheromones: ResMut<Pheromones>,
+    mut deposits: Local<ThreadLocal<RefCell<Vec<PhDeposit>>>>,
+    walls: Res<WallGrid>,
+) {
+    ant_query
+        .par_iter()
+        .for_each(|(entity, transform, ant_task, colony_id, ph_strength)| {
+            // Hungry ants don't know where the food is
+            let is_hungry = matches!(ant_task.0, AntTask::ReturnToEat);
+            if is_hungry || walls.is_wall(transform.translation.truncate()) {
+                return;
+            }
+
+            deposits.get_or_default().borrow_mut().push(PhDeposit {
+                ant: entity,
+                colony_id: *colony_id,
+                task: ant_task.0,
+                pos: (
+                    transform.translation.x as i32,
+                    transform.translation.y as i32,
+                ),
+                strength: ph_strength.0,
+            });
+        });
+
+    // Emitted in ant order, whichever thread collected them
+    let mut all_deposits: Vec<_> = deposits
+        .iter_mut()
+        .flat_map(|d| d.get_mut().drain(..))
+        .collect();
+    all_deposits.sort_unstable_by_key(|d| d.ant);
+
+    for deposit in all_deposits {
+        let channel_id = match deposit.task {
+            AntTask::FindFood => ChannelId::TO_HOME,
+            AntTask::FindHome => ChannelId::TO_FOOD,
+            AntTask::ReturnToEat => continue,
+        };
+        pheromones
+            .colony_mut(deposit.colony_id)
+            .channel_mut(channel_id)
+            .emit_signal(&deposit.pos, deposit.strength);
+    }
+}
+
+fn update_scan_radius(mut scan_radius: ResMut<AntScanRadius>, config: Res<SimConfig>) {
+    let config = &config.ants;
+    if scan_radius.0 > config.initial_ph_scan_radius * config.ph_scan_radius_scale {
         return;
     }
 
-    scan_radius.0 += ANT_PH_SCAN_RADIUS_INCREMENT;
+    scan_radius.0 += config.ph_scan_radius_increment;
+}
+
+/// Keeps the scan radius within the configured range when the config is reloaded
+fn clamp_scan_radius(mut scan_radius: ResMut<AntScanRadius>, config: Res<SimConfig>) {
+    let config = &config.ants;
+    let min_radius = config.initial_ph_scan_radius;
+    let max_radius = min_radius * config.ph_scan_radius_scale.max(1.0);
+    scan_radius.0 = scan_radius.0.clamp(min_radius, max_radius);
 }
 
 fn update_camera_follow_pos(
@@ -140,180 +357,486 @@ fn update_stats(
     stats.num_ants = ant_query.iter().len();
 }
 
-fn decay_ph_strength(mut ant_query: Query<&mut PhStrength, With<Ant>>) {
+fn decay_ph_strength(mut ant_query: Query<&mut PhStrength, With<Ant>>, config: Res<SimConfig>) {
     for mut ph_strength in ant_query.iter_mut() {
-        ph_strength.0 = f32::max(ph_strength.0 - ANT_PH_STRENGTH_DECAY_RATE, 0.0);
+        ph_strength.0 = f32::max(ph_strength.0 - config.ants.ph_strength_decay_rate, 0.0);
+    }
+}
+
+fn drain_energy(
+    mut commands: Commands,
+    mut ant_query: Query<(Entity, &mut Energy, &mut CurrentTask, &ColonyId)>,
+    colony_query: Query<&Colony>,
+    mut stats: ResMut<SimStatistics>,
+    config: Res<SimConfig>,
+) {
+    let interval = config.ticks_to_secs(config.secs_to_ticks(0.1));
+    let drain = config.energy.drain_rate * interval;
+
+    for (entity, mut energy, mut ant_task, colony_id) in ant_query.iter_mut() {
+        energy.0 -= drain;
+        if energy.0 <= 0.0 {
+            commands.entity(entity).despawn();
+            stats.ants_starved += 1;
+            continue;
+        }
+
+        // Go back to eat while there's food stored at the nest
+        let is_hungry = energy.0 < config.energy.max_energy * 0.5;
+        let has_food_stored = colony_query
+            .iter()
+            .any(|c| c.id == *colony_id && c.food_store > 0);
+        if is_hungry && has_food_stored && matches!(ant_task.0, AntTask::FindFood) {
+            ant_task.0 = AntTask::ReturnToEat;
+        }
     }
 }
 
-fn get_steering_force(target: Vec2, current: Vec2, velocity: Vec2) -> Vec2 {
-    let desired = target - current;
-    let steering = desired - velocity;
-    steering * 0.05
+fn clear_steer_cache(mut pheromones: ResMut<Pheromones>, mut stats: ResMut<SimStatistics>) {
+    (stats.food_cache_size, stats.home_cache_size) = pheromones.clear_cache();
 }
 
 fn periodic_direction_update(
-    mut ant_query: Query<(&mut Acceleration, &Transform, &CurrentTask, &Velocity), With<Ant>>,
-    mut pheromones: ResMut<Pheromones>,
-    mut stats: ResMut<SimStatistics>,
+    mut ant_query: Query<SteeredAnt>,
+    food_query: Query<&Transform, With<FoodSource>>,
+    colony_query: Query<&Colony>,
+    pheromones: Res<Pheromones>,
+    strategies: Res<SteeringStrategies>,
     scan_radius: Res<AntScanRadius>,
+    config: Res<SimConfig>,
 ) {
-    (stats.food_cache_size, stats.home_cache_size) = pheromones.clear_cache();
+    let pull_radius = config.ants.target_auto_pull_radius;
+    let update_ticks = config.secs_to_ticks(config.ants.direction_update_interval);
 
-    for (mut acceleration, transform, current_task, velocity) in ant_query.iter_mut() {
+    let Some(strategy) = strategies.get(&config.ants.steering) else {
+        return;
+    };
+
+    ant_query.par_iter_mut().for_each_mut(|ant| {
+        let SteeredAntItem {
+            mut acceleration,
+            mut rng,
+            mut flight,
+            transform,
+            current_task,
+            colony_id,
+            caste,
+            velocity,
+            home_vector,
+        } = ant;
+        let Some(colony) = colony_query.iter().find(|c| c.id == *colony_id) else {
+            return;
+        };
+        let caste = config.castes.get(caste.0);
+        let rng = &mut rng.0;
         let current_pos = transform.translation;
         let mut target = None;
 
         // If ant is close to food/home, pull it towards itself
         match current_task.0 {
             AntTask::FindFood => {
-                let dist_to_food = transform.translation.distance_squared(vec3(
-                    FOOD_LOCATION.0,
-                    FOOD_LOCATION.1,
-                    0.0,
-                ));
-                if dist_to_food <= ANT_TARGET_AUTO_PULL_RADIUS * ANT_TARGET_AUTO_PULL_RADIUS {
-                    target = Some(vec2(FOOD_LOCATION.0, FOOD_LOCATION.1));
-                }
+                target = food_query
+                    .iter()
+                    .map(|food| food.translation)
+                    .find(|food| {
+                        transform
+                            .translation
+                            .truncate()
+                            .distance_squared(food.truncate())
+                            <= pull_radius * pull_radius
+                    })
+                    .map(|food| food.truncate());
             }
-            AntTask::FindHome => {
-                let dist_to_home = transform.translation.distance_squared(vec3(
-                    HOME_LOCATION.0,
-                    HOME_LOCATION.1,
-                    0.0,
-                ));
-                if dist_to_home <= ANT_TARGET_AUTO_PULL_RADIUS * ANT_TARGET_AUTO_PULL_RADIUS {
-                    target = Some(vec2(HOME_LOCATION.0, HOME_LOCATION.1));
+            AntTask::FindHome | AntTask::ReturnToEat => {
+                let dist_to_home = current_pos.truncate().distance_squared(colony.home);
+                if dist_to_home <= pull_radius * pull_radius {
+                    target = Some(colony.home);
                 }
             }
         };
 
-        if target.is_none() {
-            match current_task.0 {
-                AntTask::FindFood => {
-                    target = pheromones
-                        .to_food
-                        .get_steer_target(&current_pos, scan_radius.0);
-                }
-                AntTask::FindHome => {
-                    target = pheromones
-                        .to_home
-                        .get_steer_target(&current_pos, scan_radius.0);
+        let steering_force = match target {
+            Some(target) => Some(
+                get_steering_force(target, current_pos.truncate(), velocity.0)
+                    * rng.gen_range(0.4..=config.ants.steering_force_factor.max(0.4)),
+            ),
+            None => strategy
+                .steer(
+                    &SteeringInput {
+                        pos: current_pos.truncate(),
+                        velocity: velocity.0,
+                        task: current_task.0,
+                        colony,
+                        pheromones: pheromones.colony(*colony_id),
+                        scan_radius: scan_radius.0 * caste.scan_radius_scale,
+                        config: &config.ants,
+                    },
+                    rng,
+                )
+                .map(|force| force * caste.steering_scale),
+        };
+
+        match steering_force {
+            Some(force) => {
+                acceleration.0 += force;
+                // Back to exploring with a new flight once the trail is lost
+                flight.remaining = 0.0;
+            }
+            None => {
+                let distance = config.ants.speed * caste.speed_scale * update_ticks as f32;
+                acceleration.0 += get_exploration_force(
+                    &config.ants.exploration,
+                    &mut flight,
+                    velocity.0,
+                    distance,
+                    caste.randomness_scale,
+                    rng,
+                );
+
+                // No home trail found, head back along the home vector
+                let is_heading_home = !matches!(current_task.0, AntTask::FindFood);
+                if config.path_integration.enabled && is_heading_home {
+                    acceleration.0 +=
+                        -home_vector.0.normalize_or_zero() * config.path_integration.weight;
                 }
             }
         }
+    });
+}
 
-        if target.is_none() {
-            // Default direction randomization
-            acceleration.0 += get_rand_unit_vec2() * 0.2;
-            continue;
+/// Acceleration of an ant with no trail to follow, `distance` is how far
+/// the ant moves until its next direction update
+fn get_exploration_force(
+    exploration: &Exploration,
+    flight: &mut LevyFlight,
+    velocity: Vec2,
+    distance: f32,
+    randomness_scale: f32,
+    rng: &mut impl Rng,
+) -> Vec2 {
+    match *exploration {
+        Exploration::Random => get_rand_unit_vec2(rng) * 0.2 * randomness_scale,
+        Exploration::Correlated {
+            turn_deg,
+            distribution,
+        } => {
+            let turn = turn_deg.to_radians() * randomness_scale;
+            let angle = match distribution {
+                TurnDistribution::Normal => get_rand_normal(rng) * turn,
+                TurnDistribution::Uniform if turn > 0.0 => rng.gen_range(-turn..=turn),
+                TurnDistribution::Uniform => 0.0,
+            };
+            Vec2::from_angle(angle).rotate(velocity) - velocity
         }
+        Exploration::Levy {
+            exponent,
+            min_flight,
+            max_flight,
+        } => {
+            if flight.remaining <= 0.0 {
+                // Inverse transform sampling of a power law, p(l) ~ l^-exponent
+                let exponent = exponent.max(1.1);
+                let u: f32 = rng.gen();
+                let length = min_flight * (1.0 - u).powf(-1.0 / (exponent - 1.0));
+                flight.heading = get_rand_unit_vec2(rng);
+                flight.remaining = length.min(max_flight);
+            }
+            flight.remaining -= distance;
+            flight.heading - velocity
+        }
+    }
+}
+
+/// Ants turn away from the repelling channels of their colony and from the
+/// trails of other colonies, soldiers looking for food head to the alarm instead
+fn channel_steering(
+    mut ant_query: Query<(
+        &mut Acceleration,
+        &Transform,
+        &CurrentTask,
+        &ColonyId,
+        &AntCaste,
+        &Velocity,
+    )>,
+    pheromones: Res<Pheromones>,
+    scan_radius: Res<AntScanRadius>,
+    config: Res<SimConfig>,
+) {
+    let combat = &config.combat;
+    let repelling_channels: Vec<_> = pheromones
+        .channels
+        .iter()
+        .enumerate()
+        .filter(|(_, channel)| channel.repulsion > 0.0)
+        .map(|(idx, channel)| (ChannelId(idx), channel.repulsion, channel.look_ahead))
+        .collect();
+    let is_avoiding_foreign_trails =
+        pheromones.colonies.len() > 1 && combat.foreign_trail_avoidance > 0.0;
 
-        let steering_force = get_steering_force(
-            target.unwrap(),
-            transform.translation.truncate(),
-            velocity.0,
-        );
+    ant_query.par_iter_mut().for_each_mut(
+        |(mut acceleration, transform, current_task, colony_id, caste, velocity)| {
+            let pos = transform.translation.truncate();
+            let colony_ph = pheromones.colony(*colony_id);
 
-        let mut rng = rand::thread_rng();
-        acceleration.0 += steering_force * rng.gen_range(0.4..=ANT_STEERING_FORCE_FACTOR);
-    }
+            if caste.0 == Caste::Soldier && matches!(current_task.0, AntTask::FindFood) {
+                let radius = scan_radius.0 * config.castes.get(caste.0).scan_radius_scale;
+                let alarm = colony_ph.channel(ChannelId::ALARM);
+                if let Some(target) = alarm.get_steer_target(&transform.translation, radius) {
+                    acceleration.0 += get_steering_force(target, pos, velocity.0)
+                        * config.ants.steering_force_factor;
+                    return;
+                }
+            }
+
+            for (channel_id, repulsion, look_ahead) in repelling_channels.iter() {
+                let channel = colony_ph.channel(*channel_id);
+                let force = get_avoidance_force(pos, velocity.0, *look_ahead, |probe| {
+                    channel.sample_signal(probe)
+                });
+                acceleration.0 += force * *repulsion;
+            }
+
+            if is_avoiding_foreign_trails {
+                let foreign_signal = |probe: Vec2| -> f32 {
+                    pheromones
+                        .colonies
+                        .iter()
+                        .enumerate()
+                        .filter(|(idx, _)| *idx != colony_id.0)
+                        .map(|(_, c)| {
+                            // Markers of the shared food sources aren't trails
+                            c.to_home().sample_trail(probe) + c.to_food().sample_trail(probe)
+                        })
+                        .sum()
+                };
+                let force =
+                    get_avoidance_force(pos, velocity.0, combat.trail_look_ahead, foreign_signal);
+                acceleration.0 += force * combat.foreign_trail_avoidance;
+            }
+        },
+    );
 }
 
 fn check_home_food_collisions(
-    mut ant_query: Query<
-        (
-            &Transform,
-            &mut Sprite,
-            &mut Velocity,
-            &mut CurrentTask,
-            &mut PhStrength,
-            &mut Handle<Image>,
-        ),
-        With<Ant>,
-    >,
-    asset_server: Res<AssetServer>,
+    mut ant_query: Query<(
+        &Transform,
+        &ColonyId,
+        &mut Velocity,
+        &mut CurrentTask,
+        &AntCaste,
+        &mut PhStrength,
+        &mut Energy,
+    )>,
+    mut food_query: Query<(&Transform, &mut FoodSource), Without<Ant>>,
+    mut colony_query: Query<&mut Colony>,
+    mut stats: ResMut<SimStatistics>,
+    tick: Res<SimTick>,
+    config: Res<SimConfig>,
 ) {
-    for (transform, mut sprite, mut velocity, mut ant_task, mut ph_strength, mut image_handle) in
+    let energy_config = &config.energy;
+
+    for (transform, colony_id, mut velocity, mut ant_task, caste, mut ph_strength, mut energy) in
         ant_query.iter_mut()
     {
+        let Some(mut colony) = colony_query.iter_mut().find(|c| c.id == *colony_id) else {
+            continue;
+        };
+        let initial_ph_strength =
+            config.ants.initial_ph_strength * config.castes.get(caste.0).ph_strength_scale;
+
         // Home collision
-        let dist_to_home =
-            transform
-                .translation
-                .distance_squared(vec3(HOME_LOCATION.0, HOME_LOCATION.1, 0.0));
-        if dist_to_home < HOME_RADIUS * HOME_RADIUS {
+        let dist_to_home = transform
+            .translation
+            .truncate()
+            .distance_squared(colony.home);
+        if dist_to_home < colony.home_radius * colony.home_radius {
             // rebound only the ants with food
             match ant_task.0 {
                 AntTask::FindFood => {}
                 AntTask::FindHome => {
                     velocity.0 *= -1.0;
+                    stats.food_collected += 1;
+                    colony.food_store += 1;
                 }
+                AntTask::ReturnToEat => velocity.0 *= -1.0,
             }
             ant_task.0 = AntTask::FindFood;
-            ph_strength.0 = ANT_INITIAL_PH_STRENGTH;
-            *image_handle = asset_server.load(SPRITE_ANT);
-            sprite.color = Color::rgb(1.0, 1.0, 2.5);
+            ph_strength.0 = initial_ph_strength;
+
+            // Hungry ants eat from the colony store
+            let is_hungry = energy.0 < energy_config.max_energy * 0.5;
+            if energy_config.enabled && is_hungry && colony.food_store > 0 {
+                colony.food_store -= 1;
+                energy.0 = (energy.0 + energy_config.food_energy).min(energy_config.max_energy);
+            }
         }
 
         // Food Collision
-        let dist_to_food =
-            transform
+        let food = food_query.iter_mut().find(|(food_transform, food)| {
+            let dist_to_food = transform
                 .translation
-                .distance_squared(vec3(FOOD_LOCATION.0, FOOD_LOCATION.1, 0.0));
-        if dist_to_food < FOOD_PICKUP_RADIUS * FOOD_PICKUP_RADIUS {
+                .truncate()
+                .distance_squared(food_transform.translation.truncate());
+            food.amount > 0 && dist_to_food < food.radius * food.radius
+        });
+        if let Some((_, mut food)) = food {
             match ant_task.0 {
                 AntTask::FindFood => {
                     velocity.0 *= -1.0;
+                    food.amount -= 1;
+                    stats.first_food_tick.get_or_insert(tick.0);
                 }
+                // Already heading home
+                AntTask::ReturnToEat => food.amount -= 1,
                 AntTask::FindHome => {}
             }
             ant_task.0 = AntTask::FindHome;
-            ph_strength.0 = ANT_INITIAL_PH_STRENGTH;
-            *image_handle = asset_server.load(SPRITE_ANT_WITH_FOOD);
-            sprite.color = Color::rgb(1.0, 2.0, 1.0);
+            ph_strength.0 = initial_ph_strength;
         }
     }
 }
 
 fn check_wall_collision(
-    mut ant_query: Query<(&Transform, &Velocity, &mut Acceleration), With<Ant>>,
+    mut ant_query: Query<
+        (
+            &Transform,
+            &Velocity,
+            &mut Acceleration,
+            &mut EntityRng,
+            &mut LevyFlight,
+        ),
+        With<Ant>,
+    >,
+    walls: Res<WallGrid>,
+    config: Res<SimConfig>,
 ) {
-    for (transform, velocity, mut acceleration) in ant_query.iter_mut() {
-        // wall rebound
-        let border = 20.0;
-        let top_left = (-W / 2.0, H / 2.0);
-        let bottom_right = (W / 2.0, -H / 2.0);
-        let x_bound = transform.translation.x < top_left.0 + border
-            || transform.translation.x >= bottom_right.0 - border;
-        let y_bound = transform.translation.y >= top_left.1 - border
-            || transform.translation.y < bottom_right.1 + border;
-        if x_bound || y_bound {
-            let mut rng = thread_rng();
-            let target = vec2(rng.gen_range(-200.0..200.0), rng.gen_range(-200.0..200.0));
-            acceleration.0 +=
-                get_steering_force(target, transform.translation.truncate(), velocity.0);
-        }
-    }
+    let look_ahead = config.map.wall_look_ahead;
+
+    ant_query.par_iter_mut().for_each_mut(
+        |(transform, velocity, mut acceleration, mut rng, mut flight)| {
+            let rng = &mut rng.0;
+
+            // Steer towards the free side of walls ahead
+            let pos = transform.translation.truncate();
+            if walls.is_wall(pos + velocity.0 * look_ahead) {
+                let left = Vec2::from_angle(PI / 4.0).rotate(velocity.0);
+                let right = Vec2::from_angle(-PI / 4.0).rotate(velocity.0);
+                let direction = match (
+                    walls.is_wall(pos + left * look_ahead),
+                    walls.is_wall(pos + right * look_ahead),
+                ) {
+                    (false, false) if rng.gen_bool(0.5) => left,
+                    (false, false) => right,
+                    (false, true) => left,
+                    (true, false) => right,
+                    (true, true) => -velocity.0,
+                };
+                acceleration.0 += get_steering_force(pos + direction * look_ahead, pos, velocity.0);
+                // Flights end at obstacles
+                flight.remaining = 0.0;
+            }
+
+            // wall rebound
+            let border = 20.0;
+            let (w, h) = (config.world.width, config.world.height);
+            let top_left = (-w / 2.0, h / 2.0);
+            let bottom_right = (w / 2.0, -h / 2.0);
+            let x_bound = transform.translation.x < top_left.0 + border
+                || transform.translation.x >= bottom_right.0 - border;
+            let y_bound = transform.translation.y >= top_left.1 - border
+                || transform.translation.y < bottom_right.1 + border;
+            if x_bound || y_bound {
+                let target = vec2(rng.gen_range(-200.0..200.0), rng.gen_range(-200.0..200.0));
+                acceleration.0 +=
+                    get_steering_force(target, transform.translation.truncate(), velocity.0);
+                flight.remaining = 0.0;
+            }
+        },
+    );
 }
 
 fn update_position(
-    mut ant_query: Query<(&mut Transform, &mut Velocity, &mut Acceleration), With<Ant>>,
+    mut ant_query: Query<(&mut Transform, &mut Velocity, &mut Acceleration, &AntCaste)>,
+    walls: Res<WallGrid>,
+    config: Res<SimConfig>,
 ) {
-    for (mut transform, mut velocity, mut acceleration) in ant_query.iter_mut() {
-        let old_pos = transform.translation;
-
-        if !acceleration.0.is_nan() {
-            velocity.0 = (velocity.0 + acceleration.0).normalize();
-            let new_translation =
-                transform.translation + vec3(velocity.0.x, velocity.0.y, 0.0) * ANT_SPEED;
-            if !new_translation.is_nan() {
-                transform.translation = new_translation;
+    ant_query.par_iter_mut().for_each_mut(
+        |(mut transform, mut velocity, mut acceleration, caste)| {
+            let old_pos = transform.translation;
+            let speed = config.ants.speed * config.castes.get(caste.0).speed_scale;
+
+            if !acceleration.0.is_nan() {
+                velocity.0 = (velocity.0 + acceleration.0).normalize();
+                velocity.0 = slide_along_walls(&walls, old_pos.truncate(), velocity.0, speed);
+                let new_translation =
+                    transform.translation + vec3(velocity.0.x, velocity.0.y, 0.0) * speed;
+                if !new_translation.is_nan() {
+                    transform.translation = new_translation;
+                }
             }
-        }
 
-        acceleration.0 = Vec2::ZERO;
-        transform.rotation =
-            Quat::from_rotation_z(calc_rotation_angle(old_pos, transform.translation) + PI / 2.0);
+            acceleration.0 = Vec2::ZERO;
+            transform.rotation = Quat::from_rotation_z(
+                calc_rotation_angle(old_pos, transform.translation) + PI / 2.0,
+            );
+        },
+    );
+}
+
+/// Adds the step of every ant to its home vector, with some noise on the
+/// length and angle. Ants back in the nest start over from zero
+fn integrate_path(
+    mut ant_query: Query<(
+        &mut HomeVector,
+        &mut EntityRng,
+        &Transform,
+        &Velocity,
+        &AntCaste,
+        &ColonyId,
+    )>,
+    colony_query: Query<&Colony>,
+    config: Res<SimConfig>,
+) {
+    let noise = config.path_integration.noise.max(0.0);
+
+    ant_query.par_iter_mut().for_each_mut(
+        |(mut home_vector, mut rng, transform, velocity, caste, colony_id)| {
+            let Some(colony) = colony_query.iter().find(|c| c.id == *colony_id) else {
+                return;
+            };
+            let pos = transform.translation.truncate();
+            if pos.distance_squared(colony.home) < colony.home_radius * colony.home_radius {
+                home_vector.0 = Vec2::ZERO;
+                return;
+            }
+
+            let speed = config.ants.speed * config.castes.get(caste.0).speed_scale;
+            let mut step = velocity.0 * speed;
+            if noise > 0.0 {
+                let rng = &mut rng.0;
+                step = Vec2::from_angle(rng.gen_range(-noise..=noise)).rotate(step)
+                    * (1.0 + rng.gen_range(-noise..=noise));
+            }
+            home_vector.0 += step;
+        },
+    );
+}
+
+/// Keeps only the part of the velocity that doesn't run into a wall,
+/// ants that hit a wall head-on turn around.
+/// Ants already inside a wall move freely so they can get out
+fn slide_along_walls(walls: &WallGrid, pos: Vec2, velocity: Vec2, speed: f32) -> Vec2 {
+    if walls.is_wall(pos) || !walls.is_wall(pos + velocity * speed) {
+        return velocity;
+    }
+
+    let along_x = vec2(velocity.x, 0.0).normalize_or_zero();
+    let along_y = vec2(0.0, velocity.y).normalize_or_zero();
+    if along_x != Vec2::ZERO && !walls.is_wall(pos + along_x * speed) {
+        along_x
+    } else if along_y != Vec2::ZERO && !walls.is_wall(pos + along_y * speed) {
+        along_y
+    } else {
+        -velocity
     }
 }
@@ -0,0 +1,213 @@
+use crate::{
+    ant::{spawn_ant, Ant, AntCaste},
+    rng::SimRng,
+    sim::{on_sim_interval, SimSet, SimTick, SimUpdate},
+    *,
+};
+use bevy::{math::vec2, prelude::*, time::common_conditions::on_timer, utils::StableHashMap};
+use std::{collections::VecDeque, time::Duration};
+
+pub struct ColonyPlugin;
+
+/// Index of the colony in the config, ants carry the id of their colony
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ColonyId(pub usize);
+
+#[derive(Component)]
+pub struct Colony {
+    pub id: ColonyId,
+    pub home: Vec2,
+    pub home_radius: f32,
+    pub ant_tint: (u8, u8, u8),
+    pub population: usize,
+    /// Food brought back by the ants, eaten by the hungry ones
+    /// and spent on new ants
+    pub food_store: u32,
+    /// Ticks at which the ants being raised hatch, oldest first
+    pub brood: VecDeque<u64>,
+}
+
+/// Population and stored food of the colonies, sampled every
+/// `simulation.history_interval`
+#[derive(Resource, Default)]
+pub struct ColonyHistory {
+    pub samples: Vec<HistorySample>,
+}
+
+#[derive(Clone, Copy)]
+pub struct HistorySample {
+    /// Simulated time of the sample
+    pub secs: f32,
+    pub colony_id: ColonyId,
+    pub population: usize,
+    pub food_store: u32,
+}
+
+impl Plugin for ColonyPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SimConfig>()
+            .init_resource::<SimRng>()
+            .init_resource::<ColonyHistory>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                SimUpdate,
+                (
+                    lay_eggs
+                        .run_if(|config: Res<SimConfig>| config.reproduction.enabled)
+                        .run_if(on_sim_interval(|c| c.reproduction.spawn_interval)),
+                    hatch_brood,
+                    record_history.run_if(on_sim_interval(|c| c.simulation.history_interval)),
+                )
+                    .chain()
+                    .in_set(SimSet::Colonies),
+            )
+            .add_systems(
+                Update,
+                update_population.run_if(on_timer(Duration::from_secs_f32(1.0))),
+            )
+            // Sprites, skipped when running headless
+            .add_systems(
+                Update,
+                add_nest_sprites.run_if(resource_exists::<AssetServer>()),
+            );
+    }
+}
+
+fn setup(mut commands: Commands, config: Res<SimConfig>) {
+    for (idx, colony) in config.colonies.iter().enumerate() {
+        let home = colony.home_location;
+        commands.spawn((
+            Transform::from_xyz(home.0, home.1, 2.0),
+            Colony {
+                id: ColonyId(idx),
+                home: vec2(home.0, home.1),
+                home_radius: colony.home_radius,
+                ant_tint: colony.ant_tint,
+                population: 0,
+                food_store: colony.initial_food,
+                brood: VecDeque::new(),
+            },
+        ));
+    }
+}
+
+fn update_population(mut colony_query: Query<&mut Colony>, ant_query: Query<&ColonyId, With<Ant>>) {
+    for mut colony in colony_query.iter_mut() {
+        colony.population = ant_query.iter().filter(|id| **id == colony.id).count();
+    }
+}
+
+/// Spends stored food on a new ant, hatching `reproduction.brood_time` later
+fn lay_eggs(
+    mut colony_query: Query<&mut Colony>,
+    ant_query: Query<&ColonyId, With<Ant>>,
+    tick: Res<SimTick>,
+    config: Res<SimConfig>,
+) {
+    let repro = &config.reproduction;
+    for mut colony in colony_query.iter_mut() {
+        let population = ant_query.iter().filter(|id| **id == colony.id).count();
+        if colony.food_store < repro.ant_cost
+            || population + colony.brood.len() >= repro.max_population as usize
+        {
+            continue;
+        }
+
+        colony.food_store -= repro.ant_cost;
+        let hatch_tick = tick.0 + config.secs_to_ticks(repro.brood_time);
+        colony.brood.push_back(hatch_tick);
+    }
+}
+
+fn hatch_brood(
+    mut commands: Commands,
+    mut colony_query: Query<&mut Colony>,
+    ant_query: Query<(&ColonyId, &AntCaste)>,
+    mut rng: ResMut<SimRng>,
+    tick: Res<SimTick>,
+    config: Res<SimConfig>,
+) {
+    // Same order on every run, the rng is forked for every ant
+    let mut colonies = colony_query.iter_mut().collect::<Vec<_>>();
+    colonies.sort_by_key(|c| c.id);
+
+    for colony in colonies.iter_mut() {
+        let Some(colony_config) = config.colonies.get(colony.id.0) else {
+            continue;
+        };
+
+        let is_hatching = |colony: &Colony| colony.brood.front().is_some_and(|t| *t <= tick.0);
+        if !is_hatching(colony) {
+            continue;
+        }
+
+        // New ants keep the colony close to its caste ratios
+        let mut counts = StableHashMap::default();
+        for (_, caste) in ant_query.iter().filter(|(id, _)| **id == colony.id) {
+            *counts.entry(caste.0).or_default() += 1;
+        }
+        while is_hatching(colony) {
+            colony.brood.pop_front();
+            let caste = colony_config.castes.next_caste(&counts);
+            *counts.entry(caste).or_default() += 1;
+            spawn_ant(
+                &mut commands,
+                colony.home,
+                colony.id,
+                caste,
+                &mut rng,
+                &config,
+            );
+        }
+    }
+}
+
+fn record_history(
+    colony_query: Query<&Colony>,
+    ant_query: Query<&ColonyId, With<Ant>>,
+    mut history: ResMut<ColonyHistory>,
+    tick: Res<SimTick>,
+    config: Res<SimConfig>,
+) {
+    let mut colonies = colony_query.iter().collect::<Vec<_>>();
+    colonies.sort_by_key(|c| c.id);
+
+    for colony in colonies {
+        history.samples.push(HistorySample {
+            secs: config.ticks_to_secs(tick.0),
+            colony_id: colony.id,
+            population: ant_query.iter().filter(|id| **id == colony.id).count(),
+            food_store: colony.food_store,
+        });
+    }
+}
+
+fn add_nest_sprites(
+    mut commands: Commands,
+    colony_query: Query<(Entity, &Transform), Added<Colony>>,
+    asset_server: Res<AssetServer>,
+) {
+    for (entity, transform) in colony_query.iter() {
+        commands.entity(entity).insert(SpriteBundle {
+            texture: asset_server.load(SPRITE_ANT_COLONY),
+            sprite: Sprite {
+                color: Color::rgb(1.5, 1.5, 1.5),
+                ..default()
+            },
+            transform: transform.with_scale(Vec3::splat(HOME_SPRITE_SCALE)),
+            ..Default::default()
+        });
+    }
+}
+
+impl Colony {
+    /// Tint applied on top of the ant sprite colors
+    pub fn ant_color(&self, color: Color) -> Color {
+        let (r, g, b) = self.ant_tint;
+        Color::rgb(
+            color.r() * r as f32 / 255.0,
+            color.g() * g as f32 / 255.0,
+            color.b() * b as f32 / 255.0,
+        )
+    }
+}
@@ -0,0 +1,123 @@
+use crate::{
+    ant::{Ant, AntCaste},
+    colony::ColonyId,
+    gui::SimStatistics,
+    pheromone::{ChannelId, Pheromones},
+    rng::SimRng,
+    sim::{on_sim_interval, SimSet, SimUpdate},
+    *,
+};
+use bevy::{
+    prelude::*,
+    utils::{StableHashMap, StableHashSet},
+};
+use rand::Rng;
+
+pub struct CombatPlugin;
+
+/// Ant seen by the encounter check
+struct Fighter {
+    entity: Entity,
+    pos: Vec2,
+    colony_id: ColonyId,
+    strength: f32,
+}
+
+impl Plugin for CombatPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SimConfig>()
+            .init_resource::<SimRng>()
+            .init_resource::<SimStatistics>()
+            .add_systems(
+                SimUpdate,
+                ant_encounters
+                    .run_if(|config: Res<SimConfig>| config.colonies.len() > 1)
+                    .run_if(on_sim_interval(|c| c.combat.encounter_interval))
+                    .in_set(SimSet::Combat),
+            );
+    }
+}
+
+/// Ants of different colonies within the detection radius fight,
+/// every ant fights at most once per encounter check.
+/// Both colonies drop an alarm where the fight happened
+fn ant_encounters(
+    mut commands: Commands,
+    ant_query: Query<(Entity, &Transform, &ColonyId, &AntCaste), With<Ant>>,
+    mut pheromones: ResMut<Pheromones>,
+    mut rng: ResMut<SimRng>,
+    mut stats: ResMut<SimStatistics>,
+    config: Res<SimConfig>,
+) {
+    let radius = config.combat.detection_radius.max(1.0);
+    let bucket_of = |pos: Vec2| {
+        (
+            (pos.x / radius).floor() as i32,
+            (pos.y / radius).floor() as i32,
+        )
+    };
+
+    let mut fighters: Vec<_> = ant_query
+        .iter()
+        .map(|(entity, transform, colony_id, caste)| Fighter {
+            entity,
+            pos: transform.translation.truncate(),
+            colony_id: *colony_id,
+            strength: config.castes.get(caste.0).fight_strength.max(0.0),
+        })
+        .collect();
+    fighters.sort_unstable_by_key(|f| f.entity);
+
+    let mut buckets: StableHashMap<(i32, i32), Vec<usize>> = StableHashMap::default();
+    for (idx, fighter) in fighters.iter().enumerate() {
+        buckets.entry(bucket_of(fighter.pos)).or_default().push(idx);
+    }
+
+    let mut has_fought = StableHashSet::default();
+    for (idx, fighter) in fighters.iter().enumerate() {
+        if has_fought.contains(&idx) {
+            continue;
+        }
+
+        let (bx, by) = bucket_of(fighter.pos);
+        let opponent = (bx - 1..=bx + 1)
+            .flat_map(|x| (by - 1..=by + 1).map(move |y| (x, y)))
+            .filter_map(|bucket| buckets.get(&bucket))
+            .flatten()
+            .copied()
+            .find(|other| {
+                let other_fighter = &fighters[*other];
+                other_fighter.colony_id != fighter.colony_id
+                    && !has_fought.contains(other)
+                    && other_fighter.pos.distance_squared(fighter.pos) <= radius * radius
+            });
+        let Some(opponent_idx) = opponent else {
+            continue;
+        };
+        let opponent = &fighters[opponent_idx];
+        has_fought.insert(idx);
+        has_fought.insert(opponent_idx);
+
+        let total_strength = fighter.strength + opponent.strength;
+        let win_chance = if total_strength > 0.0 {
+            fighter.strength / total_strength
+        } else {
+            0.5
+        };
+        let loser = if rng.rng.gen_bool(win_chance as f64) {
+            opponent
+        } else {
+            fighter
+        };
+        commands.entity(loser.entity).despawn();
+        stats.ants_killed += 1;
+
+        let pos = (fighter.pos + opponent.pos) / 2.0;
+        for colony_id in [fighter.colony_id, opponent.colony_id] {
+            pheromones
+                .colony_mut(colony_id)
+                .channel_mut(ChannelId::ALARM)
+                .emit_signal(&(pos.x as i32, pos.y as i32), config.combat.alarm_strength);
+        }
+    }
+}
@@ -1,60 +1,968 @@
+use bevy::{prelude::*, time::common_conditions::on_timer};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
 // Global
+/// Window resolution, the world size is set in the config
 pub const W: f32 = 1920.0;
 pub const H: f32 = 1080.0;
 pub const BG_COLOR: (u8, u8, u8) = (0, 0, 0);
+pub const DEFAULT_CONFIG_PATH: &str = "config.toml";
+pub const CONFIG_WATCH_INTERVAL: f32 = 1.0;
+
+// Map
+pub const WALL_COLOR: (u8, u8, u8) = (70, 70, 80);
+pub const WALL_Z_INDEX: f32 = 2.5;
 
 // Ants
-pub const NUM_ANTS: u32 = 5000;
-pub const ANT_SPEED: f32 = 1.5;
-pub const ANT_DIRECTION_RANDOMNESS_DEG: f32 = 300.0;
-pub const ANT_DIRECTION_UPDATE_INTERVAL: f32 = 0.5;
 pub const ANT_SPRITE_SCALE: f32 = 0.3;
 pub const ANT_Z_INDEX: f32 = 3.0;
-pub const ANT_INITIAL_PH_STRENGTH: f32 = 32.0;
-pub const ANT_PH_STRENGTH_DECAY_RATE: f32 = 0.7;
-pub const ANT_PH_STRENGTH_DECAY_INTERVAL: f32 = 0.5;
-pub const ANT_PH_DROP_INTERVAL: f32 = 0.7;
-pub const INITIAL_ANT_PH_SCAN_RADIUS: f32 = 15.0;
-pub const ANT_PH_SCAN_RADIUS_INCREMENT: f32 = 0.1;
-pub const ANT_PH_SCAN_RADIUS_SCALE: f32 = 1.8;
-pub const ANT_STEERING_FORCE_FACTOR: f32 = 0.7;
-pub const ANT_TARGET_AUTO_PULL_RADIUS: f32 = 100.0;
 
 // Ant Colony
-pub const HOME_LOCATION: (f32, f32) = (759.0, -350.0);
-// pub const HOME_LOCATION: (f32, f32) = (300.0, -250.0);
 pub const HOME_SPRITE_SCALE: f32 = 2.5;
-pub const HOME_RADIUS: f32 = 30.0;
-
-// Pheromones
-pub const MAX_PHEROMONE_STRENGTH: f32 = 500.0;
-pub const PH_DECAY_RATE: f32 = 0.08;
-pub const PH_DECAY_INTERVAL: f32 = 0.1;
-pub const PH_IMG_UPDATE_SEC: f32 = 0.1;
-pub const PH_GRID_VIZ_MIN_STRENGTH: u8 = 1;
-pub const PH_UNIT_GRID_SIZE: usize = 5;
-pub const PH_KD_TREE_UPDATE_INTERVAL: f32 = 2.0;
-// Larger grid size causes clumps of ants following signals
-pub const PH_CACHE_GRID_SIZE: i32 = 10;
-pub const PH_COLOR_TO_FOOD: (u8, u8, u8) = (2, 79, 2);
-pub const PH_COLOR_TO_HOME: (u8, u8, u8) = (200, 81, 112);
-pub const PH_GRID_OPACITY: u8 = 255;
-
-// Path Viz
-pub const VIZ_COLOR_TO_HOME: (u8, u8, u8) = (17, 106, 123);
-pub const VIZ_COLOR_TO_FOOD: (u8, u8, u8) = (92, 46, 126);
-pub const VIZ_DECAY_RATE: f32 = 0.3;
-pub const VIZ_COLOR_STRENGTH: f32 = 8.0;
-pub const VIZ_MAX_COLOR_STRENGTH: f32 = 50.0;
 
 // Food
-// pub const FOOD_LOCATION: (f32, f32) = (-400.0, 300.0);
-pub const FOOD_LOCATION: (f32, f32) = (-750.0, 400.0);
-pub const FOOD_PICKUP_RADIUS: f32 = 30.0;
 pub const FOOD_SPRITE_SCALE: f32 = 2.0;
 
+// Hazards
+pub const HAZARD_COLOR: (u8, u8, u8) = (150, 30, 30);
+pub const HAZARD_Z_INDEX: f32 = 1.0;
+pub const PREDATOR_SPRITE_SCALE: f32 = 1.0;
+pub const PREDATOR_Z_INDEX: f32 = 3.5;
+
 // Sprites
 pub const SPRITE_ANT: &str = "ant.png";
 pub const SPRITE_ANT_WITH_FOOD: &str = "ant_with_food.png";
 pub const SPRITE_ANT_COLONY: &str = "nest.png";
 pub const SPRITE_FOOD: &str = "food.png";
+
+/// Simulation parameters, loaded from a toml file at startup.
+/// Any missing key falls back to its default value.
+#[derive(Resource, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SimConfig {
+    pub simulation: SimulationConfig,
+    pub world: WorldConfig,
+    pub map: MapConfig,
+    pub ants: AntConfig,
+    pub castes: CastesConfig,
+    pub path_integration: PathIntegrationConfig,
+    pub energy: EnergyConfig,
+    pub combat: CombatConfig,
+    pub reproduction: ReproductionConfig,
+    pub colonies: Vec<ColonyConfig>,
+    pub pheromones: PheromoneConfig,
+    pub path_viz: PathVizConfig,
+    pub food: FoodConfig,
+    pub hazards: HazardConfig,
+    pub predators: PredatorConfig,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SimulationConfig {
+    /// Seed of the simulation rng, a random one is picked when not set
+    pub seed: Option<u64>,
+    /// Simulation ticks per second of real time
+    pub tick_rate: f32,
+    /// Interval of the colony population and food store samples
+    pub history_interval: f32,
+}
+
+/// World bounds centered on the origin, independent of the window size
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WorldConfig {
+    pub width: f32,
+    pub height: f32,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MapConfig {
+    /// PNG mask stretched over the world, black pixels are walls
+    pub file: Option<PathBuf>,
+    /// How far ahead ants look for walls to steer away from
+    pub wall_look_ahead: f32,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AntConfig {
+    pub speed: f32,
+    pub direction_update_interval: f32,
+    pub initial_ph_strength: f32,
+    pub ph_strength_decay_rate: f32,
+    pub ph_strength_decay_interval: f32,
+    pub ph_drop_interval: f32,
+    pub initial_ph_scan_radius: f32,
+    pub ph_scan_radius_increment: f32,
+    pub ph_scan_radius_scale: f32,
+    /// Steering forces are scaled by a random factor from 0.4 up to this, at least 0.4
+    pub steering_force_factor: f32,
+    pub target_auto_pull_radius: f32,
+    /// Name of the steering strategy, "midpoint", "sensors" or a custom one
+    pub steering: String,
+    /// Angle between the forward sensor and the left/right ones
+    pub sensor_angle_deg: f32,
+    /// Distance of the sensors ahead of the ant
+    pub sensor_distance: f32,
+    /// How ants move when there's no trail to follow
+    pub exploration: Exploration,
+}
+
+/// Exploration pattern of the ants not following a trail
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum Exploration {
+    /// Small random nudges every direction update
+    #[default]
+    Random,
+    /// Turns by a random angle from the current heading every direction update,
+    /// `turn_deg` is the standard deviation of a normal turn or the limit of a uniform one
+    Correlated {
+        turn_deg: f32,
+        #[serde(default)]
+        distribution: TurnDistribution,
+    },
+    /// Straight flights in random directions, with flight lengths drawn from a
+    /// power law with the given `exponent`, between `min_flight` and `max_flight`
+    Levy {
+        exponent: f32,
+        min_flight: f32,
+        max_flight: f32,
+    },
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TurnDistribution {
+    #[default]
+    Normal,
+    Uniform,
+}
+
+/// Ants drain energy over time, eat from the colony food store
+/// at the nest and starve once they run out of energy
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EnergyConfig {
+    pub enabled: bool,
+    pub max_energy: f32,
+    /// Energy lost per second of simulated time
+    pub drain_rate: f32,
+    /// Energy restored by eating a single unit of food
+    pub food_energy: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Caste {
+    /// Explores with weak trail following
+    Scout,
+    /// Strongly follows the trails
+    Forager,
+    Soldier,
+}
+
+/// Per caste multipliers of the `[ants]` values
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CastesConfig {
+    pub scout: CasteConfig,
+    pub forager: CasteConfig,
+    pub soldier: CasteConfig,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CasteConfig {
+    pub speed_scale: f32,
+    /// Random wander of the ants not following a trail
+    pub randomness_scale: f32,
+    /// Strength of the pheromones dropped
+    pub ph_strength_scale: f32,
+    pub scan_radius_scale: f32,
+    /// Steering force towards the trails
+    pub steering_scale: f32,
+    /// Odds of winning a fight, relative to the opponent's
+    pub fight_strength: f32,
+}
+
+/// Ants keep track of their displacement since leaving the nest and head back
+/// along it when there's no trail home to follow
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PathIntegrationConfig {
+    pub enabled: bool,
+    /// Relative error on the length and angle of every step
+    pub noise: f32,
+    /// Steering force towards the estimated nest, the random wander is 0.2
+    pub weight: f32,
+}
+
+/// Encounters between ants of different colonies
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CombatConfig {
+    /// Ants of different colonies within this distance fight
+    pub detection_radius: f32,
+    pub encounter_interval: f32,
+    /// Alarm pheromone dropped on fights, soldiers follow it
+    pub alarm_strength: f32,
+    /// Steering force away from the trails of other colonies, 0 ignores them
+    pub foreign_trail_avoidance: f32,
+    /// How far ahead ants look for trails of other colonies
+    pub trail_look_ahead: f32,
+}
+
+/// Share of every caste in the colony, the shares don't need to add up to 1
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CasteRatios {
+    pub scout: f32,
+    pub forager: f32,
+    pub soldier: f32,
+}
+
+/// Colonies spend their stored food to raise new ants at the nest
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReproductionConfig {
+    pub enabled: bool,
+    /// Food spent on every new ant
+    pub ant_cost: u32,
+    /// Time from spending the food to the ant hatching
+    pub brood_time: f32,
+    /// Interval between new ants of a colony
+    pub spawn_interval: f32,
+    /// Colonies stop raising ants at this population
+    pub max_population: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ColonyConfig {
+    pub home_location: (f32, f32),
+    pub home_radius: f32,
+    pub num_ants: u32,
+    /// Tint applied to the colony's ant sprites
+    pub ant_tint: (u8, u8, u8),
+    /// Food in the colony store at the start
+    pub initial_food: u32,
+    pub castes: CasteRatios,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PheromoneConfig {
+    pub max_strength: f32,
+    pub evaporation: Evaporation,
+    pub decay_interval: f32,
+    /// Share of every signal spread to its neighboring cells, 0 disables diffusion
+    pub diffusion_rate: f32,
+    pub diffusion_interval: f32,
+    pub img_update_interval: f32,
+    pub grid_viz_min_strength: u8,
+    pub unit_grid_size: usize,
+    pub neighbor_index: NeighborIndexKind,
+    /// Rebuild interval of the kd tree, the bucket grid drops decayed cells instead
+    pub kd_tree_update_interval: f32,
+    /// Size of the bucket grid cells, in pheromone grid cells
+    pub bucket_size: i32,
+    /// Larger grid size causes clumps of ants following signals
+    pub cache_grid_size: i32,
+    pub grid_opacity: u8,
+    /// Storage of the pheromone and path grids
+    pub grid_storage: GridStorage,
+    /// Pheromone layers of every colony, the `to_home`, `to_food` and `alarm`
+    /// channels are always there and take their defaults when not listed
+    pub channels: Vec<ChannelConfig>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChannelConfig {
+    pub name: String,
+    pub color: (u8, u8, u8),
+    /// Overrides `pheromones.evaporation` for the channel
+    pub evaporation: Option<Evaporation>,
+    /// Steering force away from the channel's signals, 0 ignores them
+    pub repulsion: f32,
+    /// Distance ahead of the ant where repelling signals are sensed
+    pub look_ahead: f32,
+}
+
+/// How grid values evaporate every decay interval
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "model", rename_all = "snake_case")]
+pub enum Evaporation {
+    /// Subtracts `rate`, weak and strong values lose the same amount
+    Linear { rate: f32 },
+    /// Multiplies by `factor`, strong values lose more
+    Exponential { factor: f32 },
+    /// Values halve every `secs` seconds of simulated time
+    HalfLife { secs: f32 },
+}
+
+/// Backend of the pheromone neighbor queries used for steering
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NeighborIndexKind {
+    /// Rebuilt periodically, steering sees signals up to one rebuild interval old
+    #[default]
+    KdTree,
+    /// Uniform bucket grid updated as signals are emitted, always current
+    Buckets,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GridStorage {
+    /// Hashmap of the cells holding a value, grows with the trails
+    #[default]
+    Sparse,
+    /// Array covering the whole world, faster with many ants and large trails
+    Dense,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PathVizConfig {
+    pub color_to_home: (u8, u8, u8),
+    pub color_to_food: (u8, u8, u8),
+    /// Applied every tick
+    pub evaporation: Evaporation,
+    pub color_strength: f32,
+    pub max_color_strength: f32,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FoodConfig {
+    pub sources: Vec<FoodSourceConfig>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FoodSourceConfig {
+    pub location: (f32, f32),
+    /// Pickup radius, the sprite shrinks as food is picked up
+    pub radius: f32,
+    pub amount: u32,
+}
+
+/// Zones that kill the ants inside them, map pixels in `HAZARD_COLOR` are hazards too
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HazardConfig {
+    pub zones: Vec<HazardZoneConfig>,
+    /// Chance per second of an ant inside a hazard dying
+    pub kill_chance: f32,
+    /// Radius of the zones placed from the window
+    pub placed_radius: f32,
+    /// Alarm pheromone dropped where an ant dies
+    pub alarm_strength: f32,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HazardZoneConfig {
+    pub location: (f32, f32),
+    pub radius: f32,
+}
+
+/// Predators wander around and kill the ants within reach
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PredatorConfig {
+    pub count: u32,
+    pub speed: f32,
+    /// Wander angle change per tick
+    pub turn_rate_deg: f32,
+    pub kill_radius: f32,
+    /// Time between two kills of a predator
+    pub attack_interval: f32,
+}
+
+/// Watches the config file and applies changes to the running simulation
+pub struct ConfigReloadPlugin {
+    pub path: PathBuf,
+}
+
+#[derive(Resource)]
+struct ConfigFile {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(std::io::Error),
+    Parse(toml::de::Error),
+    /// A value the simulation can't run with
+    Invalid(String),
+}
+
+impl SimConfig {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let contents = fs::read_to_string(path).map_err(ConfigError::Io)?;
+        let config: Self = toml::from_str(&contents).map_err(ConfigError::Parse)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Loads the config at `path`, or the defaults if the file doesn't exist
+    pub fn load_or_default(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        if !path.as_ref().exists() {
+            return Ok(Self::default());
+        }
+
+        Self::load(path)
+    }
+
+    /// Rejects the values that would crash the simulation
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |msg: &str| Err(ConfigError::Invalid(msg.to_string()));
+
+        if !(self.simulation.tick_rate > 0.0 && self.simulation.tick_rate.is_finite()) {
+            return invalid("simulation.tick_rate must be above 0");
+        }
+        if self.world.width < 1.0 || self.world.height < 1.0 {
+            return invalid("world.width and world.height must be at least 1");
+        }
+        if self.pheromones.unit_grid_size == 0 {
+            return invalid("pheromones.unit_grid_size must be above 0");
+        }
+        if self.pheromones.cache_grid_size <= 0 {
+            return invalid("pheromones.cache_grid_size must be above 0");
+        }
+
+        Ok(())
+    }
+
+    /// Applies a reloaded config in place, except for the parameters that
+    /// can only change on restart, those keep their current value.
+    /// Returns the names of the changed restart-only parameters
+    pub fn apply_reload(&mut self, mut new: SimConfig) -> Vec<&'static str> {
+        let mut restart_required = Vec::new();
+
+        macro_rules! keep_restart_only {
+            ($($($key:ident).+),* $(,)?) => {
+                $(
+                    if self.$($key).+ != new.$($key).+ {
+                        restart_required.push(stringify!($($key).+));
+                        new.$($key).+ = self.$($key).+.clone();
+                    }
+                )*
+            };
+        }
+
+        keep_restart_only!(
+            simulation.seed,
+            simulation.tick_rate,
+            world,
+            map.file,
+            colonies,
+            pheromones.max_strength,
+            pheromones.img_update_interval,
+            pheromones.unit_grid_size,
+            pheromones.cache_grid_size,
+            pheromones.grid_storage,
+            pheromones.neighbor_index,
+            pheromones.bucket_size,
+            path_viz.max_color_strength,
+            food.sources,
+            hazards.zones,
+            predators.count,
+            path_integration.enabled,
+        );
+
+        // Colonies hold a grid per channel, only the channel settings can change
+        let channel_names = |config: &SimConfig| {
+            config
+                .pheromones
+                .channels
+                .iter()
+                .map(|c| c.name.clone())
+                .collect::<Vec<_>>()
+        };
+        if channel_names(self) != channel_names(&new) {
+            restart_required.push("pheromones.channels");
+            new.pheromones.channels = self.pheromones.channels.clone();
+        }
+
+        *self = new;
+        restart_required
+    }
+
+    /// Number of simulation ticks in `secs` of simulated time, at least 1
+    pub fn secs_to_ticks(&self, secs: f32) -> u64 {
+        (secs * self.simulation.tick_rate).round().max(1.0) as u64
+    }
+
+    pub fn ticks_to_secs(&self, ticks: u64) -> f32 {
+        ticks as f32 / self.simulation.tick_rate
+    }
+}
+
+impl Plugin for ConfigReloadPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SimConfig>()
+            .insert_resource(ConfigFile {
+                modified: file_modified_time(&self.path),
+                path: self.path.clone(),
+            })
+            .add_systems(
+                Update,
+                watch_config_file.run_if(on_timer(Duration::from_secs_f32(CONFIG_WATCH_INTERVAL))),
+            );
+    }
+}
+
+fn file_modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn watch_config_file(mut config_file: ResMut<ConfigFile>, mut config: ResMut<SimConfig>) {
+    let modified = file_modified_time(&config_file.path);
+    if modified.is_none() || modified == config_file.modified {
+        return;
+    }
+    config_file.modified = modified;
+
+    let new_config = match SimConfig::load(&config_file.path) {
+        Ok(c) => c,
+        Err(e) => {
+            warn!(
+                "{}: {e}, keeping current config",
+                config_file.path.display()
+            );
+            return;
+        }
+    };
+
+    let mut merged = config.clone();
+    let restart_required = merged.apply_reload(new_config);
+    for key in restart_required.iter() {
+        warn!("config: `{key}` can't change while running, restart to apply it");
+    }
+
+    if merged != *config {
+        info!("config: reloaded {}", config_file.path.display());
+        *config = merged;
+    }
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        Self {
+            simulation: default(),
+            world: default(),
+            map: default(),
+            ants: default(),
+            castes: default(),
+            path_integration: default(),
+            energy: default(),
+            combat: default(),
+            reproduction: default(),
+            colonies: vec![default()],
+            pheromones: default(),
+            path_viz: default(),
+            food: default(),
+            hazards: default(),
+            predators: default(),
+        }
+    }
+}
+
+impl Default for SimulationConfig {
+    fn default() -> Self {
+        Self {
+            seed: None,
+            tick_rate: 60.0,
+            history_interval: 5.0,
+        }
+    }
+}
+
+impl WorldConfig {
+    pub fn size(&self) -> Vec2 {
+        Vec2::new(self.width, self.height)
+    }
+}
+
+impl Default for WorldConfig {
+    fn default() -> Self {
+        Self {
+            width: W,
+            height: H,
+        }
+    }
+}
+
+impl Default for MapConfig {
+    fn default() -> Self {
+        Self {
+            file: None,
+            wall_look_ahead: 20.0,
+        }
+    }
+}
+
+impl Default for AntConfig {
+    fn default() -> Self {
+        Self {
+            speed: 1.5,
+            direction_update_interval: 0.5,
+            initial_ph_strength: 32.0,
+            ph_strength_decay_rate: 0.7,
+            ph_strength_decay_interval: 0.5,
+            ph_drop_interval: 0.7,
+            initial_ph_scan_radius: 15.0,
+            ph_scan_radius_increment: 0.1,
+            ph_scan_radius_scale: 1.8,
+            steering_force_factor: 0.7,
+            target_auto_pull_radius: 100.0,
+            steering: "midpoint".to_string(),
+            sensor_angle_deg: 45.0,
+            sensor_distance: 40.0,
+            exploration: Exploration::Random,
+        }
+    }
+}
+
+impl Caste {
+    pub const ALL: [Caste; 3] = [Caste::Scout, Caste::Forager, Caste::Soldier];
+}
+
+impl CastesConfig {
+    pub fn get(&self, caste: Caste) -> &CasteConfig {
+        match caste {
+            Caste::Scout => &self.scout,
+            Caste::Forager => &self.forager,
+            Caste::Soldier => &self.soldier,
+        }
+    }
+}
+
+impl Default for CastesConfig {
+    fn default() -> Self {
+        Self {
+            scout: CasteConfig {
+                speed_scale: 1.2,
+                randomness_scale: 3.0,
+                ph_strength_scale: 0.5,
+                scan_radius_scale: 0.5,
+                steering_scale: 0.3,
+                fight_strength: 0.5,
+            },
+            forager: CasteConfig::default(),
+            soldier: CasteConfig {
+                speed_scale: 0.8,
+                fight_strength: 3.0,
+                ..default()
+            },
+        }
+    }
+}
+
+impl Default for CasteConfig {
+    fn default() -> Self {
+        Self {
+            speed_scale: 1.0,
+            randomness_scale: 1.0,
+            ph_strength_scale: 1.0,
+            scan_radius_scale: 1.0,
+            steering_scale: 1.0,
+            fight_strength: 1.0,
+        }
+    }
+}
+
+impl CasteRatios {
+    pub fn get(&self, caste: Caste) -> f32 {
+        match caste {
+            Caste::Scout => self.scout,
+            Caste::Forager => self.forager,
+            Caste::Soldier => self.soldier,
+        }
+    }
+}
+
+impl Default for CasteRatios {
+    fn default() -> Self {
+        Self {
+            scout: 0.0,
+            forager: 1.0,
+            soldier: 0.0,
+        }
+    }
+}
+
+impl Default for PathIntegrationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            noise: 0.05,
+            weight: 0.5,
+        }
+    }
+}
+
+impl Default for CombatConfig {
+    fn default() -> Self {
+        Self {
+            detection_radius: 8.0,
+            encounter_interval: 0.2,
+            alarm_strength: 100.0,
+            foreign_trail_avoidance: 0.5,
+            trail_look_ahead: 30.0,
+        }
+    }
+}
+
+impl Default for EnergyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_energy: 100.0,
+            drain_rate: 0.5,
+            food_energy: 50.0,
+        }
+    }
+}
+
+impl Default for ReproductionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ant_cost: 5,
+            brood_time: 10.0,
+            spawn_interval: 0.2,
+            max_population: 10000,
+        }
+    }
+}
+
+impl Default for ColonyConfig {
+    fn default() -> Self {
+        Self {
+            home_location: (759.0, -350.0),
+            // home_location: (300.0, -250.0),
+            home_radius: 30.0,
+            num_ants: 5000,
+            ant_tint: (255, 255, 255),
+            initial_food: 0,
+            castes: default(),
+        }
+    }
+}
+
+impl Default for PheromoneConfig {
+    fn default() -> Self {
+        Self {
+            max_strength: 500.0,
+            evaporation: Evaporation::Linear { rate: 0.08 },
+            decay_interval: 0.1,
+            diffusion_rate: 0.0,
+            diffusion_interval: 0.1,
+            img_update_interval: 0.1,
+            grid_viz_min_strength: 1,
+            unit_grid_size: 5,
+            neighbor_index: NeighborIndexKind::KdTree,
+            kd_tree_update_interval: 2.0,
+            bucket_size: 16,
+            cache_grid_size: 10,
+            grid_opacity: 255,
+            grid_storage: GridStorage::Sparse,
+            channels: vec![
+                ChannelConfig {
+                    name: "to_home".to_string(),
+                    color: (200, 81, 112),
+                    ..default()
+                },
+                ChannelConfig {
+                    name: "to_food".to_string(),
+                    color: (2, 79, 2),
+                    ..default()
+                },
+                ChannelConfig {
+                    name: "alarm".to_string(),
+                    color: (230, 180, 30),
+                    evaporation: Some(Evaporation::HalfLife { secs: 3.0 }),
+                    repulsion: 1.0,
+                    ..default()
+                },
+            ],
+        }
+    }
+}
+
+impl Default for ChannelConfig {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            color: (255, 255, 255),
+            evaporation: None,
+            repulsion: 0.0,
+            look_ahead: 40.0,
+        }
+    }
+}
+
+impl Default for PathVizConfig {
+    fn default() -> Self {
+        Self {
+            color_to_home: (17, 106, 123),
+            color_to_food: (92, 46, 126),
+            evaporation: Evaporation::Linear { rate: 0.3 },
+            color_strength: 8.0,
+            max_color_strength: 50.0,
+        }
+    }
+}
+
+impl Default for FoodConfig {
+    fn default() -> Self {
+        Self {
+            sources: vec![FoodSourceConfig {
+                // location: (-400.0, 300.0),
+                location: (-750.0, 400.0),
+                ..default()
+            }],
+        }
+    }
+}
+
+impl Default for FoodSourceConfig {
+    fn default() -> Self {
+        Self {
+            location: (0.0, 0.0),
+            radius: 30.0,
+            amount: 5000,
+        }
+    }
+}
+
+impl Default for HazardConfig {
+    fn default() -> Self {
+        Self {
+            zones: Vec::new(),
+            kill_chance: 0.5,
+            placed_radius: 40.0,
+            alarm_strength: 100.0,
+        }
+    }
+}
+
+impl Default for HazardZoneConfig {
+    fn default() -> Self {
+        Self {
+            location: (0.0, 0.0),
+            radius: 40.0,
+        }
+    }
+}
+
+impl Default for PredatorConfig {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            speed: 1.0,
+            turn_rate_deg: 10.0,
+            kill_radius: 12.0,
+            attack_interval: 1.0,
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "failed to read config: {e}"),
+            ConfigError::Parse(e) => write!(f, "failed to parse config: {e}"),
+            ConfigError::Invalid(e) => write!(f, "invalid config: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reload_keeps_and_reports_restart_only_keys() {
+        let mut config = SimConfig::default();
+        let mut new = config.clone();
+        new.ants.speed = 3.0;
+        new.pheromones.diffusion_rate = 0.1;
+        new.simulation.tick_rate = 30.0;
+        new.pheromones.unit_grid_size = 10;
+        new.colonies[0].num_ants = 10;
+
+        let restart_required = config.apply_reload(new);
+
+        assert_eq!(
+            restart_required,
+            vec![
+                "simulation.tick_rate",
+                "colonies",
+                "pheromones.unit_grid_size"
+            ]
+        );
+        assert_eq!(config.simulation.tick_rate, 60.0);
+        assert_eq!(config.pheromones.unit_grid_size, 5);
+        assert_eq!(config.colonies, SimConfig::default().colonies);
+        // Live keys apply
+        assert_eq!(config.ants.speed, 3.0);
+        assert_eq!(config.pheromones.diffusion_rate, 0.1);
+    }
+
+    #[test]
+    fn reload_keeps_channels_but_applies_their_settings() {
+        let mut config = SimConfig::default();
+        let mut new = config.clone();
+        new.pheromones.channels[2].repulsion = 2.0;
+        assert!(config.apply_reload(new).is_empty());
+        assert_eq!(config.pheromones.channels[2].repulsion, 2.0);
+
+        let mut new = config.clone();
+        new.pheromones.channels.push(ChannelConfig {
+            name: "danger".to_string(),
+            ..default()
+        });
+        assert_eq!(config.apply_reload(new), vec!["pheromones.channels"]);
+        assert_eq!(config.pheromones.channels.len(), 3);
+    }
+
+    #[test]
+    fn validate_rejects_values_that_crash() {
+        assert!(SimConfig::default().validate().is_ok());
+
+        let mut config = SimConfig::default();
+        config.simulation.tick_rate = 0.0;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+
+        let mut config = SimConfig::default();
+        config.pheromones.unit_grid_size = 0;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+
+        let mut config = SimConfig::default();
+        config.pheromones.cache_grid_size = 0;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+    }
+
+    #[test]
+    fn unchanged_reload_requires_no_restart() {
+        let mut config = SimConfig::default();
+        assert!(config.apply_reload(SimConfig::default()).is_empty());
+        assert_eq!(config, SimConfig::default());
+    }
+}
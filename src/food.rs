@@ -0,0 +1,107 @@
+use crate::{
+    gui::SimStatistics,
+    pheromone::{ChannelId, Pheromones},
+    sim::{SimSet, SimUpdate},
+    *,
+};
+use bevy::{prelude::*, time::common_conditions::on_timer};
+use std::time::Duration;
+
+pub struct FoodPlugin;
+
+/// Food source with a finite amount of food, it's despawned along with
+/// its marker on the food trails once ants have picked up all of it
+#[derive(Component)]
+pub struct FoodSource {
+    pub radius: f32,
+    pub amount: u32,
+    pub initial_amount: u32,
+}
+
+impl Plugin for FoodPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SimConfig>()
+            .init_resource::<SimStatistics>()
+            .add_systems(Startup, setup)
+            .add_systems(SimUpdate, despawn_exhausted_food.after(SimSet::Ants))
+            .add_systems(
+                Update,
+                update_stats.run_if(on_timer(Duration::from_secs_f32(1.0))),
+            )
+            // Sprites, skipped when running headless
+            .add_systems(
+                Update,
+                (add_food_sprites, update_food_sprite_size)
+                    .chain()
+                    .run_if(resource_exists::<AssetServer>()),
+            );
+    }
+}
+
+fn setup(mut commands: Commands, config: Res<SimConfig>) {
+    for source in config.food.sources.iter() {
+        commands.spawn((
+            Transform::from_xyz(source.location.0, source.location.1, 2.0),
+            FoodSource {
+                radius: source.radius,
+                amount: source.amount,
+                initial_amount: source.amount,
+            },
+        ));
+    }
+}
+
+fn despawn_exhausted_food(
+    mut commands: Commands,
+    food_query: Query<(Entity, &Transform, &FoodSource)>,
+    mut pheromones: ResMut<Pheromones>,
+) {
+    for (entity, transform, food) in food_query.iter() {
+        if food.amount > 0 {
+            continue;
+        }
+
+        commands.entity(entity).despawn();
+        let pos = (
+            transform.translation.x as i32,
+            transform.translation.y as i32,
+        );
+        for colony_ph in pheromones.colonies.iter_mut() {
+            colony_ph
+                .channel_mut(ChannelId::TO_FOOD)
+                .remove_marker(&pos);
+        }
+    }
+}
+
+fn update_stats(mut stats: ResMut<SimStatistics>, food_query: Query<&FoodSource>) {
+    stats.num_food_sources = food_query.iter().len();
+    stats.food_remaining = food_query.iter().map(|f| f.amount).sum();
+}
+
+fn add_food_sprites(
+    mut commands: Commands,
+    food_query: Query<(Entity, &Transform), Added<FoodSource>>,
+    asset_server: Res<AssetServer>,
+) {
+    for (entity, transform) in food_query.iter() {
+        commands.entity(entity).insert(SpriteBundle {
+            texture: asset_server.load(SPRITE_FOOD),
+            sprite: Sprite {
+                color: Color::rgb(1.5, 1.5, 1.5),
+                ..default()
+            },
+            transform: transform.with_scale(Vec3::splat(FOOD_SPRITE_SCALE)),
+            ..Default::default()
+        });
+    }
+}
+
+fn update_food_sprite_size(
+    mut food_query: Query<(&mut Transform, &FoodSource), Changed<FoodSource>>,
+) {
+    for (mut transform, food) in food_query.iter_mut() {
+        let remaining = food.amount as f32 / food.initial_amount.max(1) as f32;
+        transform.scale = Vec3::splat(FOOD_SPRITE_SCALE * remaining);
+    }
+}
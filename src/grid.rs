@@ -1,31 +1,107 @@
 use crate::{
-    utils::{calc_weighted_midpoint, window_to_grid},
+    spatial::NeighborIndex,
+    utils::{calc_weighted_midpoint, grid_to_world, world_to_grid},
     *,
 };
-use bevy::prelude::*;
-use kd_tree::KdTree;
-use std::{cmp, collections::HashMap};
+use bevy::{math::vec3, prelude::*, utils::StableHashMap};
+use std::{cmp, mem, sync::RwLock};
+
+/// Exponential evaporation never reaches zero, smaller values are dropped
+const MIN_EVAPORATED_VALUE: f32 = 0.01;
+/// Food and home markers are far stronger than any pheromone
+const MARKER_STRENGTH: f32 = 100000.0;
+
+const NEIGHBOR_OFFSETS: [(i32, i32); 8] = [
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (-1, 0),
+    (1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+];
+
+/// Values on a grid of cells that decay over time, keyed by cell
+pub trait DecayGrid: Send + Sync {
+    /// Sets `value` on an empty cell, or adds `increment_value` to an existing one.
+    /// Cells above the max allowed value are food and home markers, they're left untouched
+    fn add_value(&mut self, key: &(i32, i32), value: f32, increment_value: f32);
+    fn get_value(&self, key: &(i32, i32)) -> Option<f32>;
+    fn remove_value(&mut self, key: &(i32, i32));
+    /// Evaporates every value, markers above the max allowed value don't evaporate
+    fn decay_values(&mut self, step: DecayStep);
+    fn drop_zero_values(&mut self);
+    /// Spreads `rate` of every value evenly over its 8 neighbors.
+    /// Values above the max allowed value are food and home markers, they stay in place
+    fn diffuse(&mut self, rate: f32);
+    /// Cells holding a value, zero values can show up until they're dropped
+    fn iter_values(&self) -> Box<dyn Iterator<Item = ((i32, i32), f32)> + '_>;
+    fn num_values(&self) -> usize;
+}
+
+/// Evaporation of a single decay step, values become `value * factor - amount`
+#[derive(Clone, Copy)]
+pub struct DecayStep {
+    factor: f32,
+    amount: f32,
+    min_value: f32,
+}
 
-pub struct DecayGrid {
+/// Hashmap backed grid, only stores the cells holding a value, unbounded
+pub struct SparseDecayGrid {
     max_allowed_value: f32,
-    values: HashMap<(i32, i32), f32>,
+    values: StableHashMap<(i32, i32), f32>,
+}
+
+/// Array backed grid covering a fixed range of keys, keys outside it are ignored
+pub struct DenseDecayGrid {
+    max_allowed_value: f32,
+    min_key: (i32, i32),
+    width: usize,
+    height: usize,
+    values: Vec<f32>,
+    /// Reused by the diffusion step
+    scratch: Vec<f32>,
 }
 
 pub struct WorldGrid {
     pub color: (u8, u8, u8),
 
-    signals: DecayGrid,
-    tree: Option<KdTree<[f32; 2]>>,
-    steer_cache: HashMap<(i32, i32), Vec2>,
+    unit_grid_size: usize,
+    cache_grid_size: i32,
+    /// Pheromones never go above it, stronger cells are markers
+    max_strength: f32,
+    world: WorldConfig,
+    signals: Box<dyn DecayGrid>,
+    index: Box<dyn NeighborIndex>,
+    /// Steer targets computed from the center of each cache cell, keyed by cell
+    /// and scan radius bits, shared by the ants in the cell and filled from parallel systems.
+    /// The radius is part of the key since it depends on the caste
+    steer_cache: RwLock<StableHashMap<(i32, i32, u32), Vec2>>,
 }
 
 impl WorldGrid {
-    pub fn new(color: (u8, u8, u8), signals: HashMap<(i32, i32), f32>) -> Self {
+    pub fn new(
+        color: (u8, u8, u8),
+        signals: Box<dyn DecayGrid>,
+        config: &PheromoneConfig,
+        world: &WorldConfig,
+    ) -> Self {
+        let mut index = config.neighbor_index.new_index(config);
+        for (key, _) in signals.iter_values() {
+            index.insert(key);
+        }
+
         Self {
             color,
-            signals: DecayGrid::new(signals, MAX_PHEROMONE_STRENGTH),
-            tree: None,
-            steer_cache: HashMap::new(),
+            unit_grid_size: config.unit_grid_size,
+            cache_grid_size: config.cache_grid_size,
+            max_strength: config.max_strength,
+            world: world.clone(),
+            signals,
+            index,
+            steer_cache: RwLock::default(),
         }
     }
 
@@ -37,36 +113,51 @@ impl WorldGrid {
             return;
         }
         self.signals.add_value(&key, value, value * 0.25);
+        self.index.insert(key);
     }
 
-    pub fn update_tree(&mut self) {
-        let mut pts = Vec::new();
-        for (k, &v) in self.signals.values.iter() {
-            if v <= 0.0 {
-                continue;
-            }
+    /// Pins a food or home marker on the cell of the world position `pos`
+    pub fn add_marker(&mut self, pos: &(i32, i32)) {
+        let key = self.get_ph_key(pos.0, pos.1);
+        self.signals.add_value(&key, MARKER_STRENGTH, 0.0);
+        self.index.insert(key);
+    }
 
-            let (x, y) = *k;
-            pts.push([x as f32, y as f32]);
-        }
+    /// Removes the marker on the cell of the world position `pos`,
+    /// it's dropped from the index on its next update
+    pub fn remove_marker(&mut self, pos: &(i32, i32)) {
+        let key = self.get_ph_key(pos.0, pos.1);
+        self.signals.remove_value(&key);
+    }
 
-        self.tree = Some(KdTree::build_by_ordered_float(pts));
+    pub fn update_index(&mut self) {
+        self.index.update(self.signals.as_ref());
     }
 
     pub fn clear_steer_cache(&mut self) -> u32 {
-        let ret = self.steer_cache.len();
-        self.steer_cache.clear();
+        let steer_cache = self.steer_cache.get_mut().unwrap();
+        let ret = steer_cache.len();
+        steer_cache.clear();
         ret as u32
     }
 
-    pub fn get_steer_target(&mut self, pos: &Vec3, radius: f32) -> Option<Vec2> {
+    pub fn get_steer_target(&self, pos: &Vec3, radius: f32) -> Option<Vec2> {
         let (x, y) = (pos.x as i32, pos.y as i32);
         let grid_pos = self.get_cache_grid_pos(x, y);
-        if let Some(v) = self.steer_cache.get(&grid_pos) {
+        let cache_key = (grid_pos.0, grid_pos.1, radius.to_bits());
+        if let Some(v) = self.steer_cache.read().unwrap().get(&cache_key) {
             return Some(*v);
         }
 
-        match self.get_ph_in_range(pos, radius) {
+        // Looked up from the cell center so the cached target doesn't
+        // depend on which ant gets there first
+        let (cx, cy) = grid_to_world(
+            grid_pos.0,
+            grid_pos.1,
+            self.cache_grid_size as usize,
+            &self.world,
+        );
+        match self.get_ph_in_range(&vec3(cx as f32, cy as f32, 0.0), radius) {
             Some(v) => {
                 // No nearby pheromone signals
                 if v.is_empty() {
@@ -74,39 +165,61 @@ impl WorldGrid {
                 }
 
                 let steer_target = calc_weighted_midpoint(&v);
-                self.steer_cache.insert(grid_pos, steer_target);
+                self.steer_cache
+                    .write()
+                    .unwrap()
+                    .insert(cache_key, steer_target);
                 Some(steer_target)
             }
             None => None,
         }
     }
 
+    /// Sum of the signals on the cell at `pos` and its neighbors
+    pub fn sample_signal(&self, pos: Vec2) -> f32 {
+        self.sample_cells(pos).sum()
+    }
+
+    /// Same as `sample_signal`, leaving out the food and home markers
+    pub fn sample_trail(&self, pos: Vec2) -> f32 {
+        self.sample_cells(pos)
+            .filter(|v| *v <= self.max_strength)
+            .sum()
+    }
+
+    fn sample_cells(&self, pos: Vec2) -> impl Iterator<Item = f32> + '_ {
+        let (x, y) = self.get_ph_key(pos.x as i32, pos.y as i32);
+        let neighbors = NEIGHBOR_OFFSETS
+            .iter()
+            .map(move |(dx, dy)| (x + dx, y + dy));
+
+        std::iter::once((x, y))
+            .chain(neighbors)
+            .filter_map(|key| self.signals.get_value(&key))
+    }
+
     fn get_ph_key(&self, x: i32, y: i32) -> (i32, i32) {
-        (x / PH_UNIT_GRID_SIZE as i32, y / PH_UNIT_GRID_SIZE as i32)
+        let size = self.unit_grid_size as i32;
+        (x / size, y / size)
     }
 
     fn get_pos_from_ph(&self, x: i32, y: i32) -> (i32, i32) {
-        (x * PH_UNIT_GRID_SIZE as i32, y * PH_UNIT_GRID_SIZE as i32)
+        let size = self.unit_grid_size as i32;
+        (x * size, y * size)
     }
 
     fn get_cache_grid_pos(&self, x: i32, y: i32) -> (i32, i32) {
-        let (tx, ty) = (x + (W as usize / 2) as i32, (H as usize / 2) as i32 - y);
-        let (tx, ty) = (tx / PH_CACHE_GRID_SIZE, ty / PH_CACHE_GRID_SIZE);
-
-        (tx, ty)
+        world_to_grid(x, y, self.cache_grid_size as usize, &self.world)
     }
 
     fn get_ph_in_range(&self, pos: &Vec3, radius: f32) -> Option<Vec<(i32, i32, f32)>> {
         let key = self.get_ph_key(pos.x as i32, pos.y as i32);
-        if let Some(t) = &self.tree {
+        if let Some(found) = self.index.within_radius(key, radius) {
             let mut ph_items = Vec::new();
-            let found = t.within_radius(&[key.0 as f32, key.1 as f32], radius);
-            for i in found.iter() {
-                let [x, y] = *i;
-                let (x, y) = (*x as i32, *y as i32);
-                if let Some(v) = self.signals.values.get(&(x, y)) {
+            for (x, y) in found {
+                if let Some(v) = self.signals.get_value(&(x, y)) {
                     let world_xy = self.get_pos_from_ph(x, y);
-                    ph_items.push((world_xy.0, world_xy.1, *v));
+                    ph_items.push((world_xy.0, world_xy.1, v));
                 }
             }
 
@@ -116,37 +229,99 @@ impl WorldGrid {
         None
     }
 
-    pub fn decay_signals(&mut self) {
-        self.signals.decay_values(PH_DECAY_RATE);
+    pub fn decay_signals(&mut self, step: DecayStep) {
+        self.signals.decay_values(step);
+    }
+
+    pub fn diffuse_signals(&mut self, rate: f32) {
+        self.signals.diffuse(rate);
+        for (key, _) in self.signals.iter_values() {
+            self.index.insert(key);
+        }
     }
 
     pub fn drop_zero_signals(&mut self) {
         self.signals.drop_zero_values();
     }
 
-    pub fn get_signals(&self) -> &HashMap<(i32, i32), f32> {
-        self.signals.get_values()
+    pub fn get_signals(&self) -> &dyn DecayGrid {
+        self.signals.as_ref()
     }
 
     pub fn get_signals_size(&self) -> usize {
-        self.signals.values.len()
+        self.signals.num_values()
     }
 }
 
-impl DecayGrid {
-    pub fn new(values: HashMap<(i32, i32), f32>, max_allowed_value: f32) -> Self {
+impl Evaporation {
+    /// Decay step applied every `interval` seconds of simulated time
+    pub fn step(&self, interval: f32) -> DecayStep {
+        match *self {
+            Evaporation::Linear { rate } => DecayStep {
+                factor: 1.0,
+                amount: rate,
+                min_value: 0.0,
+            },
+            Evaporation::Exponential { factor } => DecayStep {
+                factor,
+                amount: 0.0,
+                min_value: MIN_EVAPORATED_VALUE,
+            },
+            Evaporation::HalfLife { secs } => DecayStep {
+                factor: 0.5_f32.powf(interval / secs),
+                amount: 0.0,
+                min_value: MIN_EVAPORATED_VALUE,
+            },
+        }
+    }
+}
+
+impl DecayStep {
+    pub fn apply(&self, value: f32) -> f32 {
+        let value = value * self.factor - self.amount;
+        if value > self.min_value {
+            value
+        } else {
+            0.0
+        }
+    }
+}
+
+impl GridStorage {
+    /// Empty grid for the keys from `min_key` to `max_key` inclusive,
+    /// the bounds are only used by the dense grid
+    pub fn new_grid(
+        &self,
+        min_key: (i32, i32),
+        max_key: (i32, i32),
+        max_allowed_value: f32,
+    ) -> Box<dyn DecayGrid> {
+        match self {
+            GridStorage::Sparse => Box::new(SparseDecayGrid::new(max_allowed_value)),
+            GridStorage::Dense => {
+                Box::new(DenseDecayGrid::new(min_key, max_key, max_allowed_value))
+            }
+        }
+    }
+}
+
+impl SparseDecayGrid {
+    pub fn new(max_allowed_value: f32) -> Self {
         Self {
-            values,
+            values: StableHashMap::default(),
             max_allowed_value,
         }
     }
+}
 
-    pub fn add_value(&mut self, key: &(i32, i32), value: f32, increment_value: f32) {
+impl DecayGrid for SparseDecayGrid {
+    fn add_value(&mut self, key: &(i32, i32), value: f32, increment_value: f32) {
         if value <= 0.0 {
             return;
         }
 
         match self.values.get_mut(key) {
+            Some(old_value) if *old_value > self.max_allowed_value => {}
             Some(old_value) => {
                 *old_value = (increment_value + *old_value).min(self.max_allowed_value);
             }
@@ -156,47 +331,334 @@ impl DecayGrid {
         }
     }
 
-    pub fn decay_values(&mut self, decay_rate: f32) {
+    fn get_value(&self, key: &(i32, i32)) -> Option<f32> {
+        self.values.get(key).copied()
+    }
+
+    fn remove_value(&mut self, key: &(i32, i32)) {
+        self.values.remove(key);
+    }
+
+    fn decay_values(&mut self, step: DecayStep) {
         for (_, v) in self.values.iter_mut() {
-            *v = f32::max(*v - decay_rate, 0.0);
+            if *v <= self.max_allowed_value {
+                *v = step.apply(*v);
+            }
         }
     }
 
-    pub fn drop_zero_values(&mut self) {
+    fn drop_zero_values(&mut self) {
         self.values.retain(|_, v| *v > 0.0);
     }
 
-    pub fn get_values(&self) -> &HashMap<(i32, i32), f32> {
-        &self.values
+    fn diffuse(&mut self, rate: f32) {
+        let mut diffused = StableHashMap::default();
+        for (key, &v) in self.values.iter() {
+            if v <= 0.0 {
+                continue;
+            }
+            if v > self.max_allowed_value {
+                *diffused.entry(*key).or_insert(0.0) += v;
+                continue;
+            }
+
+            *diffused.entry(*key).or_insert(0.0) += v * (1.0 - rate);
+            let spread = v * rate / NEIGHBOR_OFFSETS.len() as f32;
+            for (dx, dy) in NEIGHBOR_OFFSETS {
+                *diffused.entry((key.0 + dx, key.1 + dy)).or_insert(0.0) += spread;
+            }
+        }
+
+        self.values = diffused;
+    }
+
+    fn iter_values(&self) -> Box<dyn Iterator<Item = ((i32, i32), f32)> + '_> {
+        Box::new(self.values.iter().map(|(k, v)| (*k, *v)))
+    }
+
+    fn num_values(&self) -> usize {
+        self.values.len()
+    }
+}
+
+impl DenseDecayGrid {
+    pub fn new(min_key: (i32, i32), max_key: (i32, i32), max_allowed_value: f32) -> Self {
+        let width = (max_key.0 - min_key.0 + 1).max(0) as usize;
+        let height = (max_key.1 - min_key.1 + 1).max(0) as usize;
+
+        Self {
+            max_allowed_value,
+            min_key,
+            width,
+            height,
+            values: vec![0.0; width * height],
+            scratch: Vec::new(),
+        }
+    }
+
+    fn get_idx(&self, key: &(i32, i32)) -> Option<usize> {
+        let (x, y) = (key.0 - self.min_key.0, key.1 - self.min_key.1);
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            return None;
+        }
+
+        Some(y as usize * self.width + x as usize)
+    }
+
+    fn get_key(&self, idx: usize) -> (i32, i32) {
+        (
+            (idx % self.width) as i32 + self.min_key.0,
+            (idx / self.width) as i32 + self.min_key.1,
+        )
+    }
+}
+
+impl DecayGrid for DenseDecayGrid {
+    fn add_value(&mut self, key: &(i32, i32), value: f32, increment_value: f32) {
+        if value <= 0.0 {
+            return;
+        }
+        let Some(idx) = self.get_idx(key) else {
+            return;
+        };
+
+        let old_value = &mut self.values[idx];
+        if *old_value > self.max_allowed_value {
+            return;
+        }
+        if *old_value > 0.0 {
+            *old_value = (increment_value + *old_value).min(self.max_allowed_value);
+        } else {
+            *old_value = value;
+        }
+    }
+
+    fn get_value(&self, key: &(i32, i32)) -> Option<f32> {
+        self.get_idx(key)
+            .map(|idx| self.values[idx])
+            .filter(|v| *v > 0.0)
+    }
+
+    fn remove_value(&mut self, key: &(i32, i32)) {
+        if let Some(idx) = self.get_idx(key) {
+            self.values[idx] = 0.0;
+        }
+    }
+
+    fn decay_values(&mut self, step: DecayStep) {
+        let max_allowed_value = self.max_allowed_value;
+        for v in self.values.iter_mut().filter(|v| **v <= max_allowed_value) {
+            *v = step.apply(*v);
+        }
+    }
+
+    // Empty cells are zero already
+    fn drop_zero_values(&mut self) {}
+
+    // Values spreading past the edges of the grid are lost
+    fn diffuse(&mut self, rate: f32) {
+        let (w, h) = (self.width as i32, self.height as i32);
+        self.scratch.clear();
+        self.scratch.resize(self.values.len(), 0.0);
+
+        for (idx, &v) in self.values.iter().enumerate() {
+            if v <= 0.0 {
+                continue;
+            }
+            if v > self.max_allowed_value {
+                self.scratch[idx] += v;
+                continue;
+            }
+
+            self.scratch[idx] += v * (1.0 - rate);
+            let spread = v * rate / NEIGHBOR_OFFSETS.len() as f32;
+            let (x, y) = (idx as i32 % w, idx as i32 / w);
+            for (dx, dy) in NEIGHBOR_OFFSETS {
+                let (nx, ny) = (x + dx, y + dy);
+                if nx >= 0 && ny >= 0 && nx < w && ny < h {
+                    self.scratch[(ny * w + nx) as usize] += spread;
+                }
+            }
+        }
+
+        mem::swap(&mut self.values, &mut self.scratch);
+    }
+
+    fn iter_values(&self) -> Box<dyn Iterator<Item = ((i32, i32), f32)> + '_> {
+        Box::new(
+            self.values
+                .iter()
+                .enumerate()
+                .filter(|(_, v)| **v > 0.0)
+                .map(|(idx, v)| (self.get_key(idx), *v)),
+        )
+    }
+
+    fn num_values(&self) -> usize {
+        self.values.iter().filter(|v| **v > 0.0).count()
     }
 }
 
 pub fn add_map_to_grid_img(
-    map: &HashMap<(i32, i32), f32>,
+    map: &dyn DecayGrid,
     color: &(u8, u8, u8),
-    img_bytes: &mut Vec<u8>,
+    img_bytes: &mut [u8],
     use_grid_pos: bool,
+    config: &PheromoneConfig,
+    world: &WorldConfig,
 ) {
-    let w = W as usize / PH_UNIT_GRID_SIZE;
-    for (k, v) in map.iter() {
-        let (mut x, mut y) = (k.0, k.1);
+    let grid_size = config.unit_grid_size;
+    let w = world.width as usize / grid_size;
+    for (k, v) in map.iter_values() {
+        let (mut x, mut y) = k;
 
         if use_grid_pos {
-            (x, y) = (x * PH_UNIT_GRID_SIZE as i32, y * PH_UNIT_GRID_SIZE as i32);
-            (x, y) = window_to_grid(x, y);
+            (x, y) = (x * grid_size as i32, y * grid_size as i32);
+            (x, y) = world_to_grid(x, y, grid_size, world);
         }
 
         let idx = y * w as i32 + x;
-        let strength = cmp::min((*v as u32).saturating_mul(5), u8::MAX.into()) as u8;
+        let strength = cmp::min((v as u32).saturating_mul(5), u8::MAX.into()) as u8;
 
         let idx = (idx as usize).saturating_mul(4);
-        if idx.saturating_add(3) >= img_bytes.len() || strength < PH_GRID_VIZ_MIN_STRENGTH {
+        if idx.saturating_add(3) >= img_bytes.len() || strength < config.grid_viz_min_strength {
             continue;
         }
 
-        img_bytes[idx + 3] = cmp::min(img_bytes[idx + 3].saturating_add(strength), PH_GRID_OPACITY);
+        img_bytes[idx + 3] = cmp::min(
+            img_bytes[idx + 3].saturating_add(strength),
+            config.grid_opacity,
+        );
         img_bytes[idx] = color.0;
         img_bytes[idx + 1] = color.1;
         img_bytes[idx + 2] = color.2;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    /// Non zero values of the grid, sorted by key
+    fn values(grid: &dyn DecayGrid) -> Vec<((i32, i32), f32)> {
+        let mut values: Vec<_> = grid.iter_values().filter(|(_, v)| *v > 0.0).collect();
+        values.sort_unstable_by_key(|(key, _)| *key);
+        values
+    }
+
+    fn assert_same_values(sparse: &SparseDecayGrid, dense: &DenseDecayGrid) {
+        let (sparse, dense) = (values(sparse), values(dense));
+        assert_eq!(sparse.len(), dense.len());
+        for ((sparse_key, sparse_v), (dense_key, dense_v)) in sparse.iter().zip(dense.iter()) {
+            assert_eq!(sparse_key, dense_key);
+            assert!(
+                (sparse_v - dense_v).abs() <= 1e-5 * sparse_v.max(100.0),
+                "{sparse_key:?}: {sparse_v} != {dense_v}"
+            );
+        }
+    }
+
+    /// Same random values on both grids, away from the edges of the dense one
+    fn new_grids() -> (SparseDecayGrid, DenseDecayGrid) {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut sparse = SparseDecayGrid::new(500.0);
+        let mut dense = DenseDecayGrid::new((-50, -50), (50, 50), 500.0);
+        for _ in 0..1000 {
+            let key = (rng.gen_range(-40..40), rng.gen_range(-40..40));
+            let value = rng.gen_range(1.0..100.0);
+            sparse.add_value(&key, value, value * 0.25);
+            dense.add_value(&key, value, value * 0.25);
+        }
+        // Marker, above the max allowed value
+        sparse.add_value(&(5, 5), 100000.0, 0.0);
+        dense.add_value(&(5, 5), 100000.0, 0.0);
+
+        (sparse, dense)
+    }
+
+    #[test]
+    fn dense_grid_matches_sparse_on_add() {
+        let (sparse, dense) = new_grids();
+        assert_same_values(&sparse, &dense);
+        assert_eq!(sparse.get_value(&(5, 5)), dense.get_value(&(5, 5)));
+        assert_eq!(dense.get_value(&(60, 0)), None);
+    }
+
+    #[test]
+    fn dense_grid_matches_sparse_on_decay() {
+        let (mut sparse, mut dense) = new_grids();
+        let steps = [
+            Evaporation::Linear { rate: 5.0 }.step(0.1),
+            Evaporation::Exponential { factor: 0.9 }.step(0.1),
+            Evaporation::HalfLife { secs: 0.5 }.step(0.1),
+        ];
+        for step in steps.iter().cycle().take(30) {
+            sparse.decay_values(*step);
+            dense.decay_values(*step);
+            sparse.drop_zero_values();
+            dense.drop_zero_values();
+            assert_same_values(&sparse, &dense);
+        }
+    }
+
+    #[test]
+    fn dense_grid_matches_sparse_on_diffuse() {
+        let (mut sparse, mut dense) = new_grids();
+        for _ in 0..5 {
+            sparse.diffuse(0.2);
+            dense.diffuse(0.2);
+            assert_same_values(&sparse, &dense);
+        }
+        // Markers don't spread
+        assert!(dense.get_value(&(5, 5)).is_some_and(|v| v >= 100000.0));
+    }
+
+    #[test]
+    fn emit_keeps_markers() {
+        let config = PheromoneConfig::default();
+        let world = WorldConfig::default();
+        for storage in [GridStorage::Sparse, GridStorage::Dense] {
+            let signals = storage.new_grid((-100, -100), (100, 100), config.max_strength);
+            let mut grid = WorldGrid::new((0, 0, 0), signals, &config, &world);
+            grid.add_marker(&(100, 50));
+            for _ in 0..10 {
+                grid.emit_signal(&(100, 50), config.max_strength);
+            }
+
+            let key = grid.get_ph_key(100, 50);
+            assert_eq!(grid.get_signals().get_value(&key), Some(MARKER_STRENGTH));
+        }
+    }
+
+    #[test]
+    fn markers_dont_evaporate() {
+        let models = [
+            Evaporation::Linear { rate: 0.08 },
+            Evaporation::Exponential { factor: 0.99 },
+            Evaporation::HalfLife { secs: 5.0 },
+        ];
+        for evaporation in models {
+            let step = evaporation.step(0.1);
+            let (mut sparse, mut dense) = new_grids();
+            // 10 minutes of decay steps
+            for _ in 0..6000 {
+                sparse.decay_values(step);
+                dense.decay_values(step);
+                sparse.drop_zero_values();
+            }
+
+            assert_eq!(sparse.get_value(&(5, 5)), Some(100000.0), "{evaporation:?}");
+            assert_eq!(dense.get_value(&(5, 5)), Some(100000.0), "{evaporation:?}");
+        }
+    }
+
+    #[test]
+    fn dense_grid_matches_sparse_on_remove() {
+        let (mut sparse, mut dense) = new_grids();
+        sparse.remove_value(&(5, 5));
+        dense.remove_value(&(5, 5));
+        assert_same_values(&sparse, &dense);
+        assert_eq!(dense.get_value(&(5, 5)), None);
+    }
+}
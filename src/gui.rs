@@ -1,6 +1,17 @@
-use crate::{ant::Ant, *};
+use crate::{
+    ant::Ant,
+    colony::{Colony, ColonyHistory, HistorySample},
+    sim::{SimClock, SimSpeed, SimTick},
+    *,
+};
 use bevy::prelude::*;
-use bevy_egui::{egui, EguiContexts, EguiPlugin};
+use bevy_egui::{
+    egui::{
+        self,
+        plot::{Legend, Line, Plot},
+    },
+    EguiContexts, EguiPlugin,
+};
 
 pub struct GuiPlugin;
 
@@ -8,10 +19,12 @@ pub struct GuiPlugin;
 pub struct SimSettings {
     pub is_show_home_ph: bool,
     pub is_show_food_ph: bool,
+    pub is_show_other_ph: bool,
     pub is_show_ants: bool,
     pub is_camera_follow: bool,
     pub is_show_menu: bool,
     pub is_show_ants_path: bool,
+    pub is_show_history: bool,
 }
 
 #[derive(Default, Resource)]
@@ -20,6 +33,15 @@ pub struct SimStatistics {
     pub ph_food_size: u32,
     pub scan_radius: f32,
     pub num_ants: usize,
+    pub food_collected: u32,
+    pub num_food_sources: usize,
+    pub food_remaining: u32,
+    pub ants_starved: u32,
+    pub ants_killed: u32,
+    pub hazard_kills: u32,
+    pub predator_kills: u32,
+    /// Tick an ant first picked up food, to compare exploration modes
+    pub first_food_tick: Option<u64>,
     pub food_cache_size: u32,
     pub home_cache_size: u32,
 }
@@ -28,7 +50,9 @@ impl Plugin for GuiPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
         app.insert_resource(SimSettings::default())
             .insert_resource(SimStatistics::default())
+            .init_resource::<ColonyHistory>()
             .add_systems(Update, settings_dialog)
+            .add_systems(Update, history_dialog)
             .add_systems(Update, settings_toggle)
             .add_plugins(EguiPlugin)
             .add_systems(Startup, setup);
@@ -39,9 +63,24 @@ fn setup() {}
 
 fn settings_toggle(
     mut settings: ResMut<SimSettings>,
+    mut clock: ResMut<SimClock>,
     ant_query: Query<&mut Visibility, With<Ant>>,
     keys: Res<Input<KeyCode>>,
 ) {
+    if keys.just_pressed(KeyCode::Space) {
+        clock.toggle_pause();
+    }
+    if keys.just_pressed(KeyCode::Period) {
+        clock.step();
+    }
+    for (key, speed) in [KeyCode::Key1, KeyCode::Key2, KeyCode::Key3, KeyCode::Key4]
+        .into_iter()
+        .zip(SimSpeed::ALL)
+    {
+        if keys.just_pressed(key) {
+            clock.speed = speed;
+        }
+    }
     if keys.just_pressed(KeyCode::Tab) {
         settings.is_show_menu = !settings.is_show_menu;
     }
@@ -54,6 +93,9 @@ fn settings_toggle(
     if keys.just_pressed(KeyCode::P) {
         settings.is_show_ants_path = !settings.is_show_ants_path;
     }
+    if keys.just_pressed(KeyCode::G) {
+        settings.is_show_history = !settings.is_show_history;
+    }
     if keys.just_pressed(KeyCode::A) {
         settings.is_show_ants = !settings.is_show_ants;
         toggle_ant_visibility(ant_query, settings.is_show_ants);
@@ -63,7 +105,10 @@ fn settings_toggle(
 fn settings_dialog(
     mut contexts: EguiContexts,
     mut settings: ResMut<SimSettings>,
+    mut clock: ResMut<SimClock>,
+    tick: Res<SimTick>,
     stats: Res<SimStatistics>,
+    colony_query: Query<&Colony>,
     ant_query: Query<&mut Visibility, With<Ant>>,
 ) {
     if !settings.is_show_menu {
@@ -76,6 +121,25 @@ fn settings_dialog(
         .title_bar(false)
         .default_pos(egui::pos2(0.0, H))
         .show(ctx, |ui| {
+            egui::CollapsingHeader::new("Simulation")
+                .default_open(true)
+                .show(ui, |ui| {
+                    ui.label(format!("Tick: {:?}", tick.0));
+                    ui.horizontal(|ui| {
+                        let pause_label = if clock.is_paused { "Resume" } else { "Pause" };
+                        if ui.button(pause_label).clicked() {
+                            clock.toggle_pause();
+                        }
+                        if ui.button("Step").clicked() {
+                            clock.step();
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        for speed in SimSpeed::ALL {
+                            ui.selectable_value(&mut clock.speed, speed, speed.label());
+                        }
+                    });
+                });
             egui::CollapsingHeader::new("Stats")
                 .default_open(true)
                 .show(ui, |ui| {
@@ -85,21 +149,90 @@ fn settings_dialog(
                     ui.label(format!("Home cache: {:?}", stats.home_cache_size));
                     ui.label(format!("Scan radius: {:?}", stats.scan_radius.round()));
                     ui.label(format!("Num ants: {:?}", stats.num_ants));
+                    if colony_query.iter().len() > 1 {
+                        for colony in colony_query.iter() {
+                            ui.label(format!(
+                                "Colony {:?}: {:?} ants",
+                                colony.id.0, colony.population
+                            ));
+                        }
+                    }
+                    let food_stored: u32 = colony_query.iter().map(|c| c.food_store).sum();
+                    ui.label(format!("Food stored: {:?}", food_stored));
+                    ui.label(format!("Ants starved: {:?}", stats.ants_starved));
+                    ui.label(format!("Ants killed: {:?}", stats.ants_killed));
+                    ui.label(format!(
+                        "Killed by hazards: {:?}, predators: {:?}",
+                        stats.hazard_kills, stats.predator_kills
+                    ));
+                    ui.label(format!("Food collected: {:?}", stats.food_collected));
+                    if let Some(first_food_tick) = stats.first_food_tick {
+                        ui.label(format!("First food at tick: {:?}", first_food_tick));
+                    }
+                    ui.label(format!(
+                        "Food left: {:?} in {:?} sources",
+                        stats.food_remaining, stats.num_food_sources
+                    ));
                 });
             egui::CollapsingHeader::new("Settings")
                 .default_open(true)
                 .show(ui, |ui| {
                     ui.checkbox(&mut settings.is_show_home_ph, "Home ph");
                     ui.checkbox(&mut settings.is_show_food_ph, "Food ph");
+                    ui.checkbox(&mut settings.is_show_other_ph, "Other ph");
                     ui.checkbox(&mut settings.is_show_ants_path, "Paths");
+                    ui.checkbox(&mut settings.is_show_history, "History");
                     ui.checkbox(&mut settings.is_camera_follow, "Camera follow");
                     if ui.checkbox(&mut settings.is_show_ants, "Ants").clicked() {
                         toggle_ant_visibility(ant_query, settings.is_show_ants);
                     };
+                    ui.label("Z places a hazard zone at the cursor");
                 });
         });
 }
 
+/// Population and stored food of every colony over time
+fn history_dialog(
+    mut contexts: EguiContexts,
+    mut settings: ResMut<SimSettings>,
+    history: Res<ColonyHistory>,
+    colony_query: Query<&Colony>,
+) {
+    if !settings.is_show_history {
+        return;
+    }
+
+    let ctx = contexts.ctx_mut();
+    let history_plot = |ui: &mut egui::Ui, id: &str, value: fn(&HistorySample) -> f64| {
+        Plot::new(id)
+            .height(150.0)
+            .legend(Legend::default())
+            .include_y(0.0)
+            .allow_scroll(false)
+            .show(ui, |plot_ui| {
+                for colony in colony_query.iter() {
+                    let points: Vec<[f64; 2]> = history
+                        .samples
+                        .iter()
+                        .filter(|s| s.colony_id == colony.id)
+                        .map(|s| [s.secs as f64, value(s)])
+                        .collect();
+                    plot_ui.line(Line::new(points).name(format!("Colony {}", colony.id.0)));
+                }
+            });
+    };
+
+    egui::Window::new("Colony history")
+        .open(&mut settings.is_show_history)
+        .default_width(400.0)
+        .show(ctx, |ui| {
+            ui.label("Population");
+            history_plot(ui, "population", |s| s.population as f64);
+            ui.label("Food stored");
+            history_plot(ui, "food_store", |s| s.food_store as f64);
+        });
+}
+
 fn toggle_ant_visibility(mut ant_query: Query<&mut Visibility, With<Ant>>, is_visible: bool) {
     for mut ant in ant_query.iter_mut() {
         if is_visible {
@@ -115,10 +248,12 @@ impl Default for SimSettings {
         Self {
             is_show_home_ph: true,
             is_show_food_ph: true,
+            is_show_other_ph: true,
             is_show_ants: true,
             is_camera_follow: false,
             is_show_menu: false,
             is_show_ants_path: true,
+            is_show_history: false,
         }
     }
 }
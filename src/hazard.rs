@@ -0,0 +1,308 @@
+use crate::{
+    ant::Ant,
+    colony::ColonyId,
+    gui::SimStatistics,
+    map::WallGrid,
+    pheromone::{ChannelId, Pheromones},
+    rng::{EntityRng, SimRng},
+    sim::{on_sim_interval, SimSet, SimTick, SimUpdate},
+    utils::{calc_rotation_angle, get_rand_unit_vec2, get_rand_vec2},
+    *,
+};
+use bevy::{
+    ecs::system::SystemParam, prelude::*, sprite::MaterialMesh2dBundle, window::PrimaryWindow,
+};
+use rand::Rng;
+use std::f32::consts::PI;
+
+/// Predator spawn points inside walls are drawn again, up to this many times
+const MAX_SPAWN_TRIES: usize = 100;
+
+pub struct HazardPlugin;
+
+/// Circle that kills the ants inside it
+#[derive(Component)]
+pub struct HazardZone {
+    pub radius: f32,
+}
+
+/// Wanders around and kills the ants within reach
+#[derive(Component)]
+pub struct Predator {
+    velocity: Vec2,
+    next_attack_tick: u64,
+}
+
+/// Hazard zones and the hazards of the map
+#[derive(SystemParam)]
+struct Hazards<'w, 's> {
+    zone_query: Query<'w, 's, (&'static Transform, &'static HazardZone)>,
+    walls: Res<'w, WallGrid>,
+}
+
+impl Plugin for HazardPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SimConfig>()
+            .init_resource::<SimRng>()
+            .init_resource::<SimStatistics>()
+            .init_resource::<WallGrid>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                SimUpdate,
+                (
+                    // Spawned on the first tick, after the ants took their rngs
+                    spawn_predators.run_if(run_once()),
+                    hazard_kills.run_if(on_sim_interval(|_| 0.1)),
+                    // Predators only attack the ants still alive
+                    apply_deferred,
+                    move_predators,
+                    predator_attacks.run_if(on_sim_interval(|_| 0.1)),
+                )
+                    .chain()
+                    .in_set(SimSet::Hazards),
+            )
+            .add_systems(
+                Update,
+                place_hazard_zone.run_if(resource_exists::<Input<KeyCode>>()),
+            )
+            // Sprites, skipped when running headless
+            .add_systems(
+                Update,
+                add_hazard_sprites.run_if(resource_exists::<Assets<ColorMaterial>>()),
+            )
+            .add_systems(
+                Update,
+                add_predator_sprites.run_if(resource_exists::<AssetServer>()),
+            );
+    }
+}
+
+fn setup(mut commands: Commands, config: Res<SimConfig>) {
+    for zone in config.hazards.zones.iter() {
+        commands.spawn((
+            Transform::from_xyz(zone.location.0, zone.location.1, HAZARD_Z_INDEX),
+            HazardZone {
+                radius: zone.radius,
+            },
+        ));
+    }
+}
+
+fn spawn_predators(
+    mut commands: Commands,
+    mut rng: ResMut<SimRng>,
+    walls: Res<WallGrid>,
+    config: Res<SimConfig>,
+) {
+    for _ in 0..config.predators.count {
+        let pos = (0..MAX_SPAWN_TRIES)
+            .map(|_| get_rand_vec2(&mut rng.rng, &config.world))
+            .find(|pos| !walls.is_wall(*pos));
+        let Some(pos) = pos else {
+            warn!("no free spot found for a predator, it's not spawned");
+            continue;
+        };
+
+        commands.spawn((
+            Transform::from_xyz(pos.x, pos.y, PREDATOR_Z_INDEX),
+            Predator {
+                velocity: get_rand_unit_vec2(&mut rng.rng),
+                next_attack_tick: 0,
+            },
+            rng.fork(),
+        ));
+    }
+}
+
+/// Ants in a hazard zone or on a hazard of the map die,
+/// dropping an alarm for their colony where they died
+fn hazard_kills(
+    mut commands: Commands,
+    ant_query: Query<(Entity, &Transform, &ColonyId), With<Ant>>,
+    hazards: Hazards,
+    mut pheromones: ResMut<Pheromones>,
+    mut rng: ResMut<SimRng>,
+    mut stats: ResMut<SimStatistics>,
+    config: Res<SimConfig>,
+) {
+    let mut victims: Vec<_> = ant_query
+        .iter()
+        .map(|(entity, transform, colony_id)| (entity, transform.translation.truncate(), colony_id))
+        .filter(|(_, pos, _)| hazards.contains(*pos))
+        .collect();
+    if victims.is_empty() {
+        return;
+    }
+    victims.sort_unstable_by_key(|(entity, _, _)| *entity);
+
+    let interval = config.ticks_to_secs(config.secs_to_ticks(0.1));
+    let kill_chance = (config.hazards.kill_chance * interval).clamp(0.0, 1.0) as f64;
+    for (entity, pos, colony_id) in victims {
+        if !rng.rng.gen_bool(kill_chance) {
+            continue;
+        }
+
+        commands.entity(entity).despawn();
+        stats.hazard_kills += 1;
+        pheromones
+            .colony_mut(*colony_id)
+            .channel_mut(ChannelId::ALARM)
+            .emit_signal(&(pos.x as i32, pos.y as i32), config.hazards.alarm_strength);
+    }
+}
+
+impl Hazards<'_, '_> {
+    fn contains(&self, pos: Vec2) -> bool {
+        self.walls.is_hazard(pos)
+            || self.zone_query.iter().any(|(transform, zone)| {
+                transform.translation.truncate().distance_squared(pos) <= zone.radius * zone.radius
+            })
+    }
+}
+
+/// Random wander, predators turn around at walls and the world border
+fn move_predators(
+    mut predator_query: Query<(&mut Transform, &mut Predator, &mut EntityRng)>,
+    walls: Res<WallGrid>,
+    config: Res<SimConfig>,
+) {
+    let predators = &config.predators;
+    let max_turn = predators.turn_rate_deg.to_radians();
+    let (half_w, half_h) = (config.world.width / 2.0, config.world.height / 2.0);
+
+    for (mut transform, mut predator, mut rng) in predator_query.iter_mut() {
+        let turn = if max_turn > 0.0 {
+            rng.0.gen_range(-max_turn..=max_turn)
+        } else {
+            0.0
+        };
+        predator.velocity = Vec2::from_angle(turn).rotate(predator.velocity);
+
+        let old_pos = transform.translation;
+        let new_pos = old_pos.truncate() + predator.velocity * predators.speed;
+        let is_outside = new_pos.x.abs() >= half_w || new_pos.y.abs() >= half_h;
+        if is_outside || walls.is_wall(new_pos) {
+            predator.velocity *= -1.0;
+            continue;
+        }
+
+        transform.translation = new_pos.extend(old_pos.z);
+        transform.rotation =
+            Quat::from_rotation_z(calc_rotation_angle(old_pos, transform.translation) + PI / 2.0);
+    }
+}
+
+/// Every predator kills the closest ant within reach, then rests for the attack interval
+fn predator_attacks(
+    mut commands: Commands,
+    mut predator_query: Query<(Entity, &Transform, &mut Predator)>,
+    ant_query: Query<(Entity, &Transform, &ColonyId), With<Ant>>,
+    mut pheromones: ResMut<Pheromones>,
+    mut stats: ResMut<SimStatistics>,
+    tick: Res<SimTick>,
+    config: Res<SimConfig>,
+) {
+    let kill_radius = config.predators.kill_radius;
+    let mut predators: Vec<_> = predator_query.iter_mut().collect();
+    predators.sort_unstable_by_key(|(entity, _, _)| *entity);
+
+    let mut killed = Vec::new();
+    for (_, transform, predator) in predators.iter_mut() {
+        if tick.0 < predator.next_attack_tick {
+            continue;
+        }
+
+        let pos = transform.translation.truncate();
+        let victim = ant_query
+            .iter()
+            .filter(|(entity, _, _)| !killed.contains(entity))
+            .map(|(entity, t, colony_id)| {
+                (
+                    entity,
+                    t.translation.truncate().distance_squared(pos),
+                    colony_id,
+                )
+            })
+            .filter(|(_, dist, _)| *dist <= kill_radius * kill_radius)
+            .min_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+        let Some((entity, _, colony_id)) = victim else {
+            continue;
+        };
+
+        killed.push(entity);
+        commands.entity(entity).despawn();
+        stats.predator_kills += 1;
+        predator.next_attack_tick = tick.0 + config.secs_to_ticks(config.predators.attack_interval);
+        pheromones
+            .colony_mut(*colony_id)
+            .channel_mut(ChannelId::ALARM)
+            .emit_signal(&(pos.x as i32, pos.y as i32), config.hazards.alarm_strength);
+    }
+}
+
+/// `Z` places a hazard zone at the cursor
+fn place_hazard_zone(
+    mut commands: Commands,
+    keys: Res<Input<KeyCode>>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    camera_query: Query<(&Camera, &GlobalTransform)>,
+    config: Res<SimConfig>,
+) {
+    if !keys.just_pressed(KeyCode::Z) {
+        return;
+    }
+
+    let (Ok(window), Ok((camera, camera_transform))) =
+        (window_query.get_single(), camera_query.get_single())
+    else {
+        return;
+    };
+    let Some(pos) = window
+        .cursor_position()
+        .and_then(|cursor| camera.viewport_to_world_2d(camera_transform, cursor))
+    else {
+        return;
+    };
+
+    commands.spawn((
+        Transform::from_xyz(pos.x, pos.y, HAZARD_Z_INDEX),
+        HazardZone {
+            radius: config.hazards.placed_radius,
+        },
+    ));
+}
+
+fn add_hazard_sprites(
+    mut commands: Commands,
+    zone_query: Query<(Entity, &Transform, &HazardZone), Added<HazardZone>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    for (entity, transform, zone) in zone_query.iter() {
+        let (r, g, b) = HAZARD_COLOR;
+        commands.entity(entity).insert(MaterialMesh2dBundle {
+            mesh: meshes.add(shape::Circle::new(zone.radius).into()).into(),
+            material: materials.add(ColorMaterial::from(Color::rgba_u8(r, g, b, 160))),
+            transform: *transform,
+            ..default()
+        });
+    }
+}
+
+fn add_predator_sprites(
+    mut commands: Commands,
+    predator_query: Query<(Entity, &Transform), Added<Predator>>,
+    asset_server: Res<AssetServer>,
+) {
+    for (entity, transform) in predator_query.iter() {
+        commands.entity(entity).insert(SpriteBundle {
+            texture: asset_server.load(SPRITE_ANT),
+            sprite: Sprite {
+                color: Color::rgb(3.0, 0.5, 0.5),
+                ..default()
+            },
+            transform: transform.with_scale(Vec3::splat(PREDATOR_SPRITE_SCALE)),
+            ..Default::default()
+        });
+    }
+}
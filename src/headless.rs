@@ -0,0 +1,162 @@
+use crate::{
+    ant::{Ant, AntCaste, AntTask, CurrentTask},
+    colony::{Colony, ColonyHistory, ColonyId},
+    food::FoodSource,
+    gui::SimStatistics,
+    pheromone::Pheromones,
+    rng::SimRng,
+    sim::{SimTick, SimTimestep},
+    Caste, SimConfig,
+};
+use bevy::{app::AppExit, ecs::system::SystemParam, prelude::*};
+use std::time::Instant;
+
+/// Most history samples printed per colony, evenly spread over the run
+const MAX_HISTORY_ROWS: usize = 10;
+
+/// Runs the simulation for a fixed number of ticks as fast as possible,
+/// then prints a summary and exits.
+/// Meant to be used with `MinimalPlugins`, no window or renderer
+pub struct HeadlessPlugin {
+    pub ticks: u64,
+}
+
+#[derive(Resource)]
+struct HeadlessRun {
+    max_ticks: u64,
+    started: Instant,
+}
+
+impl Plugin for HeadlessPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(HeadlessRun {
+            max_ticks: self.ticks,
+            started: Instant::now(),
+        })
+        .add_systems(Startup, lock_timestep)
+        .add_systems(Last, count_ticks);
+    }
+}
+
+fn lock_timestep(mut timestep: ResMut<SimTimestep>) {
+    timestep.is_frame_locked = true;
+}
+
+/// Everything printed in the summary at the end of the run
+#[derive(SystemParam)]
+struct RunSummary<'w, 's> {
+    tick: Res<'w, SimTick>,
+    rng: Res<'w, SimRng>,
+    config: Res<'w, SimConfig>,
+    stats: Res<'w, SimStatistics>,
+    pheromones: Res<'w, Pheromones>,
+    history: Res<'w, ColonyHistory>,
+    ant_query:
+        Query<'w, 's, (&'static CurrentTask, &'static ColonyId, &'static AntCaste), With<Ant>>,
+    food_query: Query<'w, 's, &'static FoodSource>,
+    colony_query: Query<'w, 's, &'static Colony>,
+}
+
+fn count_ticks(run: Res<HeadlessRun>, summary: RunSummary, mut exit: EventWriter<AppExit>) {
+    if summary.tick.0 < run.max_ticks {
+        return;
+    }
+
+    println!("Headless run finished");
+    println!("  elapsed: {:.2?}", run.started.elapsed());
+    summary.print();
+
+    exit.send(AppExit);
+}
+
+impl RunSummary<'_, '_> {
+    fn print(&self) {
+        let num_with_food = self
+            .ant_query
+            .iter()
+            .filter(|(t, _, _)| matches!(t.0, AntTask::FindHome))
+            .count();
+
+        println!("  seed: {}", self.rng.seed);
+        println!("  ticks: {}", self.tick.0);
+        println!(
+            "  pheromones: {:?} grid, {:?} index",
+            self.config.pheromones.grid_storage, self.config.pheromones.neighbor_index
+        );
+        println!("  steering: {}", self.config.ants.steering);
+        println!(
+            "  ants: {} ({num_with_food} carrying food)",
+            self.ant_query.iter().len()
+        );
+        let castes = Caste::ALL.map(|caste| {
+            let count = self
+                .ant_query
+                .iter()
+                .filter(|(_, _, c)| c.0 == caste)
+                .count();
+            format!("{count} {caste:?}s")
+        });
+        println!("  castes: {}", castes.join(", ").to_lowercase());
+        println!("  food collected: {}", self.stats.food_collected);
+        match self.stats.first_food_tick {
+            Some(tick) => println!(
+                "  first food found: {:.1}s (tick {tick})",
+                self.config.ticks_to_secs(tick)
+            ),
+            None => println!("  first food found: never"),
+        }
+        if self.config.energy.enabled {
+            println!("  ants starved: {}", self.stats.ants_starved);
+        }
+        if self.config.colonies.len() > 1 {
+            println!("  ants killed: {}", self.stats.ants_killed);
+        }
+        println!(
+            "  killed by hazards: {}, predators: {}",
+            self.stats.hazard_kills, self.stats.predator_kills
+        );
+        println!(
+            "  food left: {} in {} sources",
+            self.food_query.iter().map(|f| f.amount).sum::<u32>(),
+            self.food_query.iter().len()
+        );
+        for (idx, colony_ph) in self.pheromones.colonies.iter().enumerate() {
+            let population = self
+                .ant_query
+                .iter()
+                .filter(|(_, id, _)| id.0 == idx)
+                .count();
+            let (food_store, brood) = self
+                .colony_query
+                .iter()
+                .find(|c| c.id.0 == idx)
+                .map_or((0, 0), |c| (c.food_store, c.brood.len()));
+            println!(
+                "  colony {idx}: {population} ants, {brood} in brood, {food_store} food stored, home ph: {}, food ph: {}",
+                colony_ph.to_home().get_signals_size(),
+                colony_ph.to_food().get_signals_size()
+            );
+        }
+        self.print_history();
+    }
+
+    fn print_history(&self) {
+        for idx in 0..self.pheromones.colonies.len() {
+            let samples = self
+                .history
+                .samples
+                .iter()
+                .filter(|s| s.colony_id.0 == idx)
+                .collect::<Vec<_>>();
+            let step = samples.len().div_ceil(MAX_HISTORY_ROWS).max(1);
+
+            println!("  colony {idx} history:");
+            for sample in samples.iter().step_by(step) {
+                println!(
+                    "    {:>7.1}s: {} ants, {} food stored",
+                    sample.secs, sample.population, sample.food_store
+                );
+            }
+        }
+    }
+}
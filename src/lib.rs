@@ -1,9 +1,19 @@
 pub mod ant;
+pub mod colony;
+pub mod combat;
 pub mod configs;
+pub mod food;
 pub mod grid;
 pub mod gui;
+pub mod hazard;
+pub mod headless;
+pub mod map;
 pub mod pathviz;
 pub mod pheromone;
+pub mod rng;
+pub mod sim;
+pub mod spatial;
+pub mod steering;
 pub mod utils;
 
 pub use configs::*;
@@ -1,8 +1,15 @@
 use ants::{
     ant::{AntFollowCameraPos, AntPlugin},
+    colony::ColonyPlugin,
+    combat::CombatPlugin,
+    food::FoodPlugin,
     gui::{GuiPlugin, SimSettings},
+    hazard::HazardPlugin,
+    headless::HeadlessPlugin,
+    map::{MapPlugin, WallGrid},
     pathviz::PathVizPlugin,
     pheromone::PheromonePlugin,
+    sim::SimPlugin,
     *,
 };
 use bevy::{
@@ -12,11 +19,60 @@ use bevy::{
     prelude::*,
 };
 use bevy_pancam::{PanCam, PanCamPlugin};
+use std::{env, path::PathBuf, process};
+
+const DEFAULT_HEADLESS_TICKS: u64 = 10_000;
 
 #[derive(Component)]
 struct FollowCamera;
 
+struct CliArgs {
+    /// `None` when not given, the default config is optional
+    config_path: Option<String>,
+    is_headless: bool,
+    ticks: u64,
+    seed: Option<u64>,
+}
+
 fn main() {
+    let args = CliArgs::parse();
+    let config_path = args
+        .config_path
+        .clone()
+        .unwrap_or_else(|| DEFAULT_CONFIG_PATH.to_string());
+    // Only the default config falls back to the defaults, a given path must exist
+    let config = match args.config_path {
+        Some(_) => SimConfig::load(&config_path),
+        None => SimConfig::load_or_default(&config_path),
+    };
+    let mut config = match config {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{config_path}: {e}");
+            process::exit(1);
+        }
+    };
+
+    if args.seed.is_some() {
+        config.simulation.seed = args.seed;
+    }
+
+    let walls = match &config.map.file {
+        Some(path) => match WallGrid::load(path, config.world.size()) {
+            Ok(walls) => walls,
+            Err(e) => {
+                eprintln!("{}: failed to load map: {e}", path.display());
+                process::exit(1);
+            }
+        },
+        None => WallGrid::default(),
+    };
+
+    if args.is_headless {
+        run_headless(config, walls, args.ticks);
+        return;
+    }
+
     App::new()
         .add_plugins(
             DefaultPlugins
@@ -43,17 +99,75 @@ fn main() {
             BG_COLOR.0, BG_COLOR.1, BG_COLOR.2, 0,
         )))
         .insert_resource(Msaa::Off)
+        .insert_resource(config)
+        .insert_resource(walls)
+        .add_plugins(ConfigReloadPlugin {
+            path: PathBuf::from(config_path),
+        })
         // Systems
         .add_systems(Startup, setup)
         .add_systems(Update, ant_follow_camera)
         // Internal Plugins
+        .add_plugins(SimPlugin)
+        .add_plugins(MapPlugin)
         .add_plugins(AntPlugin)
+        .add_plugins(ColonyPlugin)
+        .add_plugins(CombatPlugin)
+        .add_plugins(FoodPlugin)
+        .add_plugins(HazardPlugin)
         .add_plugins(PheromonePlugin)
         .add_plugins(PathVizPlugin)
         .add_plugins(GuiPlugin)
         .run();
 }
 
+/// Runs only the simulation logic, no window, renderer or gui
+fn run_headless(config: SimConfig, walls: WallGrid, ticks: u64) {
+    App::new()
+        .add_plugins(MinimalPlugins)
+        .insert_resource(config)
+        .insert_resource(walls)
+        .add_plugins(SimPlugin)
+        .add_plugins(MapPlugin)
+        .add_plugins(AntPlugin)
+        .add_plugins(ColonyPlugin)
+        .add_plugins(CombatPlugin)
+        .add_plugins(FoodPlugin)
+        .add_plugins(HazardPlugin)
+        .add_plugins(PheromonePlugin)
+        .add_plugins(HeadlessPlugin { ticks })
+        .run();
+}
+
+impl CliArgs {
+    /// Usage: `ants [--config <path>] [--seed <n>] [--headless] [--ticks <n>]`
+    fn parse() -> Self {
+        let args: Vec<String> = env::args().collect();
+        let value_of = |name: &str| {
+            args.iter()
+                .position(|arg| arg == name)
+                .and_then(|idx| args.get(idx + 1))
+                .cloned()
+        };
+
+        let parse_u64 = |name: &str| match value_of(name).map(|v| v.parse()) {
+            Some(Ok(value)) => Some(value),
+            Some(Err(e)) => {
+                eprintln!("{name}: {e}");
+                process::exit(1);
+            }
+            None => None,
+        };
+
+        Self {
+            config_path: value_of("--config"),
+            is_headless: args.iter().any(|arg| arg == "--headless"),
+            ticks: parse_u64("--ticks").unwrap_or(DEFAULT_HEADLESS_TICKS),
+            seed: parse_u64("--seed"),
+        }
+    }
+}
+
 fn ant_follow_camera(
     ant_pos: Res<AntFollowCameraPos>,
     sim_settings: Res<SimSettings>,
@@ -67,7 +181,17 @@ fn ant_follow_camera(
     transform.translation = vec3(ant_pos.0.x, ant_pos.0.y, ANT_Z_INDEX);
 }
 
-fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+fn setup(mut commands: Commands, config: Res<SimConfig>) {
+    // Panning and zooming out stay within the world bounds
+    let (w, h) = (config.world.width, config.world.height);
+    let pan_cam = PanCam {
+        min_x: Some(-w / 2.0),
+        max_x: Some(w / 2.0),
+        min_y: Some(-h / 2.0),
+        max_y: Some(h / 2.0),
+        ..default()
+    };
+
     commands
         .spawn((
             Camera2dBundle {
@@ -81,29 +205,5 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
             BloomSettings::default(),
             FollowCamera,
         ))
-        .insert(PanCam::default());
-
-    // Ant colony sprite
-    commands.spawn(SpriteBundle {
-        texture: asset_server.load(SPRITE_ANT_COLONY),
-        sprite: Sprite {
-            color: Color::rgb(1.5, 1.5, 1.5),
-            ..default()
-        },
-        transform: Transform::from_xyz(HOME_LOCATION.0, HOME_LOCATION.1, 2.0)
-            .with_scale(Vec3::splat(HOME_SPRITE_SCALE)),
-        ..Default::default()
-    });
-
-    // Food sprite
-    commands.spawn(SpriteBundle {
-        texture: asset_server.load(SPRITE_FOOD),
-        sprite: Sprite {
-            color: Color::rgb(1.5, 1.5, 1.5),
-            ..default()
-        },
-        transform: Transform::from_xyz(FOOD_LOCATION.0, FOOD_LOCATION.1, 2.0)
-            .with_scale(Vec3::splat(FOOD_SPRITE_SCALE)),
-        ..Default::default()
-    });
+        .insert(pan_cam);
 }
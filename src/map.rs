@@ -0,0 +1,127 @@
+use crate::*;
+use bevy::{
+    math::vec2,
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+};
+use image::{ImageError, Pixel};
+use std::path::Path;
+
+/// Walls are loaded with `WallGrid::load` before the app is built, so a bad map
+/// is reported like a bad config. Without a `WallGrid` resource there are no walls
+pub struct MapPlugin;
+
+/// Collision grid loaded from the map mask, one cell per mask pixel.
+/// The mask is stretched over the whole world, an empty grid has no walls
+#[derive(Resource, Default)]
+pub struct WallGrid {
+    /// Mask pixels in `HAZARD_COLOR`, they aren't walls
+    hazards: Vec<bool>,
+    width: usize,
+    height: usize,
+    /// World position of the top left corner of the mask
+    origin: Vec2,
+    /// World size covered by a single mask pixel
+    cell_size: Vec2,
+    cells: Vec<bool>,
+}
+
+impl Plugin for MapPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SimConfig>()
+            .init_resource::<WallGrid>()
+            .add_systems(Startup, setup.run_if(resource_exists::<Assets<Image>>()));
+    }
+}
+
+fn setup(
+    mut commands: Commands,
+    mut textures: ResMut<Assets<Image>>,
+    walls: Res<WallGrid>,
+    config: Res<SimConfig>,
+) {
+    if walls.cells.is_empty() {
+        return;
+    }
+
+    let bytes = walls
+        .cells
+        .iter()
+        .zip(walls.hazards.iter())
+        .flat_map(|cell| match cell {
+            (true, _) => [WALL_COLOR.0, WALL_COLOR.1, WALL_COLOR.2, 255],
+            (_, true) => [HAZARD_COLOR.0, HAZARD_COLOR.1, HAZARD_COLOR.2, 255],
+            _ => [0, 0, 0, 0],
+        })
+        .collect();
+    let walls_img = Image::new(
+        Extent3d {
+            width: walls.width as u32,
+            height: walls.height as u32,
+            ..Default::default()
+        },
+        TextureDimension::D2,
+        bytes,
+        TextureFormat::Rgba8Unorm,
+    );
+
+    commands.spawn(SpriteBundle {
+        texture: textures.add(walls_img),
+        sprite: Sprite {
+            custom_size: Some(config.world.size()),
+            ..default()
+        },
+        transform: Transform::from_xyz(0.0, 0.0, WALL_Z_INDEX),
+        ..Default::default()
+    });
+}
+
+impl WallGrid {
+    /// Loads a PNG mask covering a world of `size` centered on the origin,
+    /// dark opaque pixels are walls and `HAZARD_COLOR` pixels are hazards
+    pub fn load(path: impl AsRef<Path>, size: Vec2) -> Result<Self, ImageError> {
+        let mask = image::open(path)?.into_rgba8();
+        let (width, height) = (mask.width() as usize, mask.height() as usize);
+
+        let (r, g, b) = HAZARD_COLOR;
+        let hazards: Vec<_> = mask.pixels().map(|p| p.0 == [r, g, b, 255]).collect();
+        let cells = mask
+            .pixels()
+            .zip(hazards.iter())
+            .map(|(p, is_hazard)| !is_hazard && p.to_luma()[0] < 128 && p[3] >= 128)
+            .collect();
+
+        Ok(Self {
+            hazards,
+            width,
+            height,
+            origin: vec2(-size.x / 2.0, size.y / 2.0),
+            cell_size: vec2(size.x / width as f32, size.y / height as f32),
+            cells,
+        })
+    }
+
+    /// Whether the world position is inside a wall, false outside the map
+    pub fn is_wall(&self, pos: Vec2) -> bool {
+        self.get_cell(pos).is_some_and(|idx| self.cells[idx])
+    }
+
+    /// Whether the world position is on a hazard of the map
+    pub fn is_hazard(&self, pos: Vec2) -> bool {
+        self.get_cell(pos).is_some_and(|idx| self.hazards[idx])
+    }
+
+    fn get_cell(&self, pos: Vec2) -> Option<usize> {
+        if self.cells.is_empty() {
+            return None;
+        }
+
+        let x = ((pos.x - self.origin.x) / self.cell_size.x).floor();
+        let y = ((self.origin.y - pos.y) / self.cell_size.y).floor();
+        if x < 0.0 || y < 0.0 || x >= self.width as f32 || y >= self.height as f32 {
+            return None;
+        }
+
+        Some(y as usize * self.width + x as usize)
+    }
+}
@@ -2,7 +2,8 @@ use crate::{
     ant::{Ant, AntTask, CurrentTask},
     grid::{add_map_to_grid_img, DecayGrid},
     gui::SimSettings,
-    utils::window_to_grid,
+    sim::{SimSet, SimUpdate},
+    utils::world_to_grid,
     *,
 };
 use bevy::{
@@ -10,14 +11,14 @@ use bevy::{
     render::render_resource::{Extent3d, TextureDimension, TextureFormat},
     time::common_conditions::on_timer,
 };
-use std::{collections::HashMap, time::Duration};
+use std::time::Duration;
 
 pub struct PathVizPlugin;
 
 #[derive(Resource)]
 pub struct PathVizGrid {
-    pub dg_home: DecayGrid,
-    pub dg_food: DecayGrid,
+    pub dg_home: Box<dyn DecayGrid>,
+    pub dg_food: Box<dyn DecayGrid>,
 }
 
 #[derive(Component)]
@@ -25,9 +26,12 @@ struct PathVizImageRender;
 
 impl Plugin for PathVizPlugin {
     fn build(&self, app: &mut App) {
+        app.init_resource::<SimConfig>();
+        let config = app.world.resource::<SimConfig>().clone();
+
         app.add_systems(Startup, setup)
-            .insert_resource(PathVizGrid::new())
-            .add_systems(Update, update_grid_values)
+            .insert_resource(PathVizGrid::new(&config))
+            .add_systems(SimUpdate, update_grid_values.after(SimSet::Ants))
             .add_systems(
                 Update,
                 update_viz_grid_visibility.run_if(on_timer(Duration::from_secs_f32(1.0))),
@@ -39,11 +43,11 @@ impl Plugin for PathVizPlugin {
     }
 }
 
-fn setup(mut commands: Commands) {
+fn setup(mut commands: Commands, config: Res<SimConfig>) {
     commands.spawn((
         SpriteBundle {
             transform: Transform::from_xyz(0.0, 0.0, 1.0)
-                .with_scale(Vec3::splat(PH_UNIT_GRID_SIZE as f32)),
+                .with_scale(Vec3::splat(config.pheromones.unit_grid_size as f32)),
             ..Default::default()
         },
         PathVizImageRender,
@@ -65,51 +69,65 @@ fn update_viz_grid_visibility(
 fn update_grid_values(
     ant_query: Query<(&Transform, &CurrentTask), With<Ant>>,
     mut viz_grid: ResMut<PathVizGrid>,
+    config: Res<SimConfig>,
 ) {
+    let viz_config = &config.path_viz;
     for (transform, current_task) in ant_query.iter() {
         let x = transform.translation.x as i32;
         let y = transform.translation.y as i32;
-        let key = window_to_grid(x, y);
+        let key = world_to_grid(x, y, config.pheromones.unit_grid_size, &config.world);
 
         match current_task.0 {
             AntTask::FindFood => {
-                viz_grid.dg_food.add_value(&key, VIZ_COLOR_STRENGTH, 5.0);
+                viz_grid
+                    .dg_food
+                    .add_value(&key, viz_config.color_strength, 5.0);
             }
             AntTask::FindHome => {
-                viz_grid.dg_home.add_value(&key, VIZ_COLOR_STRENGTH, 5.0);
+                viz_grid
+                    .dg_home
+                    .add_value(&key, viz_config.color_strength, 5.0);
             }
+            AntTask::ReturnToEat => {}
         }
     }
 
-    viz_grid.dg_food.decay_values(VIZ_DECAY_RATE);
+    let step = viz_config.evaporation.step(config.ticks_to_secs(1));
+    viz_grid.dg_food.decay_values(step);
     viz_grid.dg_food.drop_zero_values();
-    viz_grid.dg_home.decay_values(VIZ_DECAY_RATE);
+    viz_grid.dg_home.decay_values(step);
     viz_grid.dg_home.drop_zero_values();
 }
 
 fn update_path_viz_image(
     mut textures: ResMut<Assets<Image>>,
     viz_grid: Res<PathVizGrid>,
+    config: Res<SimConfig>,
     mut query: Query<&mut Handle<Image>, With<PathVizImageRender>>,
 ) {
     let mut img_handle = query.single_mut();
+    let ph_config = &config.pheromones;
     let (w, h) = (
-        W as usize / PH_UNIT_GRID_SIZE,
-        H as usize / PH_UNIT_GRID_SIZE,
+        config.world.width as usize / ph_config.unit_grid_size,
+        config.world.height as usize / ph_config.unit_grid_size,
     );
 
     let mut bytes = vec![0; w * h * 4];
     add_map_to_grid_img(
-        viz_grid.dg_food.get_values(),
-        &VIZ_COLOR_TO_FOOD,
+        viz_grid.dg_food.as_ref(),
+        &config.path_viz.color_to_food,
         &mut bytes,
         false,
+        ph_config,
+        &config.world,
     );
     add_map_to_grid_img(
-        viz_grid.dg_home.get_values(),
-        &VIZ_COLOR_TO_HOME,
+        viz_grid.dg_home.as_ref(),
+        &config.path_viz.color_to_home,
         &mut bytes,
         false,
+        ph_config,
+        &config.world,
     );
 
     let path_img = Image::new(
@@ -126,10 +144,21 @@ fn update_path_viz_image(
 }
 
 impl PathVizGrid {
-    fn new() -> Self {
+    fn new(config: &SimConfig) -> Self {
+        let ph_config = &config.pheromones;
+        let max_key = (
+            config.world.width as i32 / ph_config.unit_grid_size as i32,
+            config.world.height as i32 / ph_config.unit_grid_size as i32,
+        );
+        let new_grid = || {
+            ph_config
+                .grid_storage
+                .new_grid((0, 0), max_key, config.path_viz.max_color_strength)
+        };
+
         Self {
-            dg_home: DecayGrid::new(HashMap::new(), VIZ_MAX_COLOR_STRENGTH),
-            dg_food: DecayGrid::new(HashMap::new(), VIZ_MAX_COLOR_STRENGTH),
+            dg_home: new_grid(),
+            dg_food: new_grid(),
         }
     }
 }
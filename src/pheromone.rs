@@ -1,6 +1,8 @@
 use crate::{
+    colony::ColonyId,
     grid::{add_map_to_grid_img, WorldGrid},
     gui::{SimSettings, SimStatistics},
+    sim::{on_sim_interval, SimSet, SimUpdate},
     *,
 };
 use bevy::{
@@ -8,14 +10,31 @@ use bevy::{
     render::render_resource::{Extent3d, TextureDimension, TextureFormat},
     time::common_conditions::on_timer,
 };
-use std::{collections::HashMap, time::Duration};
+use std::time::Duration;
 
 pub struct PheromonePlugin;
 
+pub const TO_HOME_CHANNEL: &str = "to_home";
+pub const TO_FOOD_CHANNEL: &str = "to_food";
+pub const ALARM_CHANNEL: &str = "alarm";
+/// Always the first channels, in this order
+const BUILTIN_CHANNELS: [&str; 3] = [TO_HOME_CHANNEL, TO_FOOD_CHANNEL, ALARM_CHANNEL];
+
+/// Pheromone layers of every colony, indexed by `ColonyId`
 #[derive(Resource)]
 pub struct Pheromones {
-    pub to_home: WorldGrid,
-    pub to_food: WorldGrid,
+    /// Settings of every channel, indexed by `ChannelId`
+    pub channels: Vec<ChannelConfig>,
+    pub colonies: Vec<ColonyPheromones>,
+}
+
+/// Index of a channel in `Pheromones::channels`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ChannelId(pub usize);
+
+/// A grid for every channel, indexed by `ChannelId`
+pub struct ColonyPheromones {
+    pub channels: Vec<WorldGrid>,
 }
 
 #[derive(Component)]
@@ -23,82 +42,142 @@ struct PheromoneImageRender;
 
 impl Plugin for PheromonePlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, setup)
-            .insert_resource(Pheromones::new())
+        app.init_resource::<SimConfig>();
+        let config = app.world.resource::<SimConfig>().clone();
+
+        app.add_systems(Startup, setup.run_if(resource_exists::<Assets<Image>>()))
+            .init_resource::<SimStatistics>()
+            .insert_resource(Pheromones::new(&config))
             .add_systems(
-                Update,
-                pheromone_decay.run_if(on_timer(Duration::from_secs_f32(PH_DECAY_INTERVAL))),
-            )
-            .add_systems(
-                Update,
-                update_kd_tree.run_if(on_timer(Duration::from_secs_f32(
-                    PH_KD_TREE_UPDATE_INTERVAL,
-                ))),
+                SimUpdate,
+                (
+                    pheromone_decay.run_if(on_sim_interval(|c| c.pheromones.decay_interval)),
+                    pheromone_diffusion
+                        .run_if(|config: Res<SimConfig>| config.pheromones.diffusion_rate > 0.0)
+                        .run_if(on_sim_interval(|c| c.pheromones.diffusion_interval)),
+                    update_neighbor_index
+                        .run_if(on_sim_interval(|c| c.pheromones.kd_tree_update_interval)),
+                    clean_zero_signals.run_if(on_sim_interval(|_| 2.0)),
+                )
+                    .chain()
+                    .in_set(SimSet::Pheromones),
             )
             .add_systems(
                 Update,
                 update_sim_stats.run_if(on_timer(Duration::from_secs_f32(
-                    PH_KD_TREE_UPDATE_INTERVAL,
+                    config.pheromones.kd_tree_update_interval,
                 ))),
             )
             .add_systems(
                 Update,
-                clean_zero_signals.run_if(on_timer(Duration::from_secs_f32(2.0))),
+                apply_config_changes.run_if(resource_changed::<SimConfig>()),
             )
             .add_systems(
                 Update,
-                pheromone_image_update.run_if(on_timer(Duration::from_secs_f32(PH_IMG_UPDATE_SEC))),
+                pheromone_image_update
+                    .run_if(resource_exists::<Assets<Image>>())
+                    .run_if(on_timer(Duration::from_secs_f32(
+                        config.pheromones.img_update_interval,
+                    ))),
             );
     }
 }
 
-fn pheromone_decay(mut pheromones: ResMut<Pheromones>) {
-    pheromones.to_food.decay_signals();
-    pheromones.to_home.decay_signals();
+fn pheromone_decay(mut pheromones: ResMut<Pheromones>, config: Res<SimConfig>) {
+    let interval = config.ticks_to_secs(config.secs_to_ticks(config.pheromones.decay_interval));
+    let steps: Vec<_> = pheromones
+        .channels
+        .iter()
+        .map(|channel| {
+            channel
+                .evaporation
+                .unwrap_or(config.pheromones.evaporation)
+                .step(interval)
+        })
+        .collect();
+
+    for colony in pheromones.colonies.iter_mut() {
+        for (grid, step) in colony.channels.iter_mut().zip(steps.iter()) {
+            grid.decay_signals(*step);
+        }
+    }
+}
+
+fn pheromone_diffusion(mut pheromones: ResMut<Pheromones>, config: Res<SimConfig>) {
+    let rate = config.pheromones.diffusion_rate.min(1.0);
+    for colony in pheromones.colonies.iter_mut() {
+        for grid in colony.channels.iter_mut() {
+            grid.diffuse_signals(rate);
+        }
+    }
+}
+
+fn apply_config_changes(mut pheromones: ResMut<Pheromones>, config: Res<SimConfig>) {
+    let pheromones = pheromones.as_mut();
+    pheromones.channels = resolve_channels(&config.pheromones);
+    for colony in pheromones.colonies.iter_mut() {
+        for (grid, channel) in colony.channels.iter_mut().zip(pheromones.channels.iter()) {
+            grid.color = channel.color;
+        }
+    }
 }
 
 fn update_sim_stats(pheromones: Res<Pheromones>, mut stats: ResMut<SimStatistics>) {
-    stats.ph_home_size = pheromones.to_home.get_signals_size() as u32;
-    stats.ph_food_size = pheromones.to_food.get_signals_size() as u32;
+    let colonies = pheromones.colonies.iter();
+    stats.ph_home_size = colonies
+        .clone()
+        .map(|c| c.to_home().get_signals_size() as u32)
+        .sum();
+    stats.ph_food_size = colonies
+        .map(|c| c.to_food().get_signals_size() as u32)
+        .sum();
 }
 
-fn update_kd_tree(mut pheromones: ResMut<Pheromones>) {
-    pheromones.update_tree();
+fn update_neighbor_index(mut pheromones: ResMut<Pheromones>) {
+    pheromones.update_index();
 }
 
 fn clean_zero_signals(mut pheromones: ResMut<Pheromones>) {
-    pheromones.to_food.drop_zero_signals();
-    pheromones.to_home.drop_zero_signals();
+    for colony in pheromones.colonies.iter_mut() {
+        for grid in colony.channels.iter_mut() {
+            grid.drop_zero_signals();
+        }
+    }
 }
 
 fn pheromone_image_update(
     mut textures: ResMut<Assets<Image>>,
     sim_settings: Res<SimSettings>,
+    config: Res<SimConfig>,
     pheromone: Res<Pheromones>,
     mut image_handle_query: Query<&mut Handle<Image>, With<PheromoneImageRender>>,
 ) {
     let mut img_handle = image_handle_query.single_mut();
+    let (world, config) = (&config.world, &config.pheromones);
     let (w, h) = (
-        W as usize / PH_UNIT_GRID_SIZE,
-        H as usize / PH_UNIT_GRID_SIZE,
+        world.width as usize / config.unit_grid_size,
+        world.height as usize / config.unit_grid_size,
     );
     let mut bytes = vec![0; w * h * 4];
 
-    if sim_settings.is_show_home_ph {
-        add_map_to_grid_img(
-            pheromone.to_home.get_signals(),
-            &pheromone.to_home.color,
-            &mut bytes,
-            true,
-        );
-    }
-    if sim_settings.is_show_food_ph {
-        add_map_to_grid_img(
-            pheromone.to_food.get_signals(),
-            &pheromone.to_food.color,
-            &mut bytes,
-            true,
-        );
+    for colony in pheromone.colonies.iter() {
+        for (idx, grid) in colony.channels.iter().enumerate() {
+            let is_visible = match ChannelId(idx) {
+                ChannelId::TO_HOME => sim_settings.is_show_home_ph,
+                ChannelId::TO_FOOD => sim_settings.is_show_food_ph,
+                _ => sim_settings.is_show_other_ph,
+            };
+            if is_visible {
+                add_map_to_grid_img(
+                    grid.get_signals(),
+                    &grid.color,
+                    &mut bytes,
+                    true,
+                    config,
+                    world,
+                );
+            }
+        }
     }
 
     let pheromone_map = Image::new(
@@ -114,41 +193,150 @@ fn pheromone_image_update(
     *img_handle = textures.add(pheromone_map);
 }
 
-fn setup(mut commands: Commands) {
+fn setup(mut commands: Commands, config: Res<SimConfig>) {
     commands.spawn((
         SpriteBundle {
             transform: Transform::from_xyz(0.0, 0.0, 0.0)
-                .with_scale(Vec3::splat(PH_UNIT_GRID_SIZE as f32)),
+                .with_scale(Vec3::splat(config.pheromones.unit_grid_size as f32)),
             ..Default::default()
         },
         PheromoneImageRender,
     ));
 }
 
+/// The built in channels, taken from the config or the defaults,
+/// followed by the other channels of the config
+fn resolve_channels(config: &PheromoneConfig) -> Vec<ChannelConfig> {
+    let find_channel =
+        |channels: &[ChannelConfig], name: &str| channels.iter().find(|c| c.name == name).cloned();
+    let default_channels = PheromoneConfig::default().channels;
+
+    let mut channels: Vec<ChannelConfig> = BUILTIN_CHANNELS
+        .iter()
+        .filter_map(|name| {
+            find_channel(&config.channels, name).or_else(|| find_channel(&default_channels, name))
+        })
+        .collect();
+    for channel in config.channels.iter() {
+        if !channels.iter().any(|c| c.name == channel.name) {
+            channels.push(channel.clone());
+        }
+    }
+
+    channels
+}
+
+impl ChannelId {
+    pub const TO_HOME: ChannelId = ChannelId(0);
+    pub const TO_FOOD: ChannelId = ChannelId(1);
+    pub const ALARM: ChannelId = ChannelId(2);
+}
+
 impl Pheromones {
-    fn new() -> Self {
-        let mut to_food_map = HashMap::new();
-        let mut to_home_map = HashMap::new();
+    fn new(config: &SimConfig) -> Self {
+        let channels = resolve_channels(&config.pheromones);
+        let colonies = config
+            .colonies
+            .iter()
+            .map(|colony| ColonyPheromones::new(colony.home_location, &channels, config))
+            .collect();
 
-        // Food and Home have high pheromone strength
-        to_food_map.insert((FOOD_LOCATION.0 as i32, FOOD_LOCATION.1 as i32), 100000.0);
-        to_home_map.insert((HOME_LOCATION.0 as i32, HOME_LOCATION.1 as i32), 100000.0);
+        Self { channels, colonies }
+    }
 
-        Self {
-            to_food: WorldGrid::new(PH_COLOR_TO_FOOD, to_food_map),
-            to_home: WorldGrid::new(PH_COLOR_TO_HOME, to_home_map),
-        }
+    pub fn channel_id(&self, name: &str) -> Option<ChannelId> {
+        self.channels
+            .iter()
+            .position(|c| c.name == name)
+            .map(ChannelId)
     }
 
-    fn update_tree(&mut self) {
-        self.to_food.update_tree();
-        self.to_home.update_tree();
+    pub fn colony(&self, id: ColonyId) -> &ColonyPheromones {
+        &self.colonies[id.0]
     }
 
+    pub fn colony_mut(&mut self, id: ColonyId) -> &mut ColonyPheromones {
+        &mut self.colonies[id.0]
+    }
+
+    fn update_index(&mut self) {
+        for colony in self.colonies.iter_mut() {
+            for grid in colony.channels.iter_mut() {
+                grid.update_index();
+            }
+        }
+    }
+
+    /// Clears the steer caches, returns the sizes of the food and home caches
     pub fn clear_cache(&mut self) -> (u32, u32) {
-        (
-            self.to_food.clear_steer_cache(),
-            self.to_home.clear_steer_cache(),
-        )
+        let (mut food, mut home) = (0, 0);
+        for colony in self.colonies.iter_mut() {
+            for (idx, grid) in colony.channels.iter_mut().enumerate() {
+                let size = grid.clear_steer_cache();
+                match ChannelId(idx) {
+                    ChannelId::TO_FOOD => food += size,
+                    ChannelId::TO_HOME => home += size,
+                    _ => {}
+                }
+            }
+        }
+
+        (food, home)
+    }
+}
+
+impl ColonyPheromones {
+    fn new(home: (f32, f32), channels: &[ChannelConfig], config: &SimConfig) -> Self {
+        let ph_config = &config.pheromones;
+        let (max_x, max_y) = (
+            config.world.width as i32 / 2 / ph_config.unit_grid_size as i32,
+            config.world.height as i32 / 2 / ph_config.unit_grid_size as i32,
+        );
+
+        let channels = channels
+            .iter()
+            .enumerate()
+            .map(|(idx, channel)| {
+                let signals = ph_config.grid_storage.new_grid(
+                    (-max_x, -max_y),
+                    (max_x, max_y),
+                    ph_config.max_strength,
+                );
+                let mut grid = WorldGrid::new(channel.color, signals, ph_config, &config.world);
+
+                // Food and Home have high pheromone strength
+                match ChannelId(idx) {
+                    ChannelId::TO_FOOD => {
+                        for food in config.food.sources.iter().map(|f| f.location) {
+                            grid.add_marker(&(food.0 as i32, food.1 as i32));
+                        }
+                    }
+                    ChannelId::TO_HOME => {
+                        grid.add_marker(&(home.0 as i32, home.1 as i32));
+                    }
+                    _ => {}
+                }
+
+                grid
+            })
+            .collect();
+
+        Self { channels }
+    }
+
+    pub fn channel(&self, id: ChannelId) -> &WorldGrid {
+        &self.channels[id.0]
+    }
+
+    pub fn channel_mut(&mut self, id: ChannelId) -> &mut WorldGrid {
+        &mut self.channels[id.0]
+    }
+
+    pub fn to_home(&self) -> &WorldGrid {
+        self.channel(ChannelId::TO_HOME)
+    }
+
+    pub fn to_food(&self) -> &WorldGrid {
+        self.channel(ChannelId::TO_FOOD)
     }
 }
@@ -0,0 +1,42 @@
+use crate::*;
+use bevy::prelude::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+/// Rng used by every random draw of the simulation,
+/// the same seed and config reproduce the same run
+#[derive(Resource)]
+pub struct SimRng {
+    pub seed: u64,
+    pub rng: StdRng,
+}
+
+/// Rng owned by a single entity, lets systems running in parallel draw
+/// random numbers without sharing the `SimRng`
+#[derive(Component)]
+pub struct EntityRng(pub StdRng);
+
+impl SimRng {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// New entity rng seeded from this one, so runs stay reproducible
+    pub fn fork(&mut self) -> EntityRng {
+        EntityRng(StdRng::seed_from_u64(self.rng.gen()))
+    }
+}
+
+impl FromWorld for SimRng {
+    fn from_world(world: &mut World) -> Self {
+        let seed = world
+            .get_resource::<SimConfig>()
+            .and_then(|c| c.simulation.seed)
+            .unwrap_or_else(|| rand::thread_rng().gen());
+        info!("simulation seed: {seed}");
+
+        Self::new(seed)
+    }
+}
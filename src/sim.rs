@@ -0,0 +1,192 @@
+use crate::*;
+use bevy::{app::RunFixedUpdateLoop, ecs::schedule::ScheduleLabel, prelude::*};
+use std::time::{Duration, Instant};
+
+/// Drops accumulated time beyond this, so a slow frame doesn't snowball
+pub const MAX_TICKS_PER_FRAME: u32 = 10;
+/// Time spent running ticks each frame at max speed
+pub const MAX_SPEED_FRAME_BUDGET: Duration = Duration::from_millis(25);
+
+pub struct SimPlugin;
+
+/// Schedule that runs a single simulation tick,
+/// it's run at a fixed rate independent of the frame rate
+#[derive(ScheduleLabel, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SimUpdate;
+
+#[derive(SystemSet, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum SimSet {
+    Ants,
+    Combat,
+    Hazards,
+    Colonies,
+    Pheromones,
+}
+
+/// Number of simulation ticks run so far
+#[derive(Resource, Default)]
+pub struct SimTick(pub u64);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SimSpeed {
+    #[default]
+    X1,
+    X2,
+    X8,
+    /// As many ticks as fit in a frame
+    Max,
+}
+
+/// Pause, single-step and speed of the simulation
+#[derive(Resource, Default)]
+pub struct SimClock {
+    pub is_paused: bool,
+    pub speed: SimSpeed,
+    pending_steps: u32,
+}
+
+#[derive(Resource)]
+pub struct SimTimestep {
+    pub period: Duration,
+    /// Run exactly one tick per frame, regardless of the elapsed time
+    pub is_frame_locked: bool,
+    accumulator: Duration,
+}
+
+impl Plugin for SimPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SimConfig>();
+        let tick_rate = app.world.resource::<SimConfig>().simulation.tick_rate;
+
+        app.init_schedule(SimUpdate)
+            .configure_sets(
+                SimUpdate,
+                (
+                    SimSet::Ants,
+                    SimSet::Combat,
+                    SimSet::Hazards,
+                    SimSet::Colonies,
+                    SimSet::Pheromones,
+                )
+                    .chain(),
+            )
+            // Bevy doesn't sync between sets, despawned ants must be gone
+            // before the next set so they can't die twice or still fight
+            .add_systems(
+                SimUpdate,
+                (
+                    apply_deferred.after(SimSet::Ants).before(SimSet::Combat),
+                    apply_deferred.after(SimSet::Combat).before(SimSet::Hazards),
+                    apply_deferred
+                        .after(SimSet::Hazards)
+                        .before(SimSet::Colonies),
+                ),
+            )
+            .init_resource::<SimTick>()
+            .init_resource::<SimClock>()
+            .insert_resource(SimTimestep::new(tick_rate))
+            .add_systems(RunFixedUpdateLoop, run_sim_ticks);
+    }
+}
+
+fn run_sim_ticks(world: &mut World) {
+    let delta = world.resource::<Time>().delta();
+    let mut clock = world.resource_mut::<SimClock>();
+    let (is_paused, speed) = (clock.is_paused, clock.speed);
+    let pending_steps = std::mem::take(&mut clock.pending_steps);
+    let mut timestep = world.resource_mut::<SimTimestep>();
+
+    let num_ticks = if timestep.is_frame_locked {
+        1
+    } else if is_paused {
+        timestep.accumulator = Duration::ZERO;
+        pending_steps
+    } else if let Some(multiplier) = speed.multiplier() {
+        timestep.ticks_to_run(delta * multiplier, MAX_TICKS_PER_FRAME * multiplier)
+    } else {
+        let start = Instant::now();
+        while start.elapsed() < MAX_SPEED_FRAME_BUDGET {
+            run_sim_tick(world);
+        }
+        0
+    };
+
+    for _ in 0..num_ticks {
+        run_sim_tick(world);
+    }
+}
+
+fn run_sim_tick(world: &mut World) {
+    world.run_schedule(SimUpdate);
+    world.resource_mut::<SimTick>().0 += 1;
+}
+
+/// Run condition for `SimUpdate` systems, true once every `interval` seconds
+/// of simulated time, the interval is read from the config on every tick
+pub fn on_sim_interval(
+    interval: fn(&SimConfig) -> f32,
+) -> impl FnMut(Res<SimTick>, Res<SimConfig>) -> bool {
+    move |tick: Res<SimTick>, config: Res<SimConfig>| {
+        tick.0
+            .is_multiple_of(config.secs_to_ticks(interval(&config)))
+    }
+}
+
+impl SimSpeed {
+    pub const ALL: [SimSpeed; 4] = [SimSpeed::X1, SimSpeed::X2, SimSpeed::X8, SimSpeed::Max];
+
+    /// Ticks run per tick period of real time, `None` for max speed
+    pub fn multiplier(&self) -> Option<u32> {
+        match self {
+            SimSpeed::X1 => Some(1),
+            SimSpeed::X2 => Some(2),
+            SimSpeed::X8 => Some(8),
+            SimSpeed::Max => None,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            SimSpeed::X1 => "1x",
+            SimSpeed::X2 => "2x",
+            SimSpeed::X8 => "8x",
+            SimSpeed::Max => "Max",
+        }
+    }
+}
+
+impl SimClock {
+    pub fn toggle_pause(&mut self) {
+        self.is_paused = !self.is_paused;
+    }
+
+    /// Pauses the simulation and runs a single tick
+    pub fn step(&mut self) {
+        self.is_paused = true;
+        self.pending_steps += 1;
+    }
+}
+
+impl SimTimestep {
+    pub fn new(tick_rate: f32) -> Self {
+        Self {
+            period: Duration::from_secs_f32(tick_rate.recip()),
+            is_frame_locked: false,
+            accumulator: Duration::ZERO,
+        }
+    }
+
+    fn ticks_to_run(&mut self, delta: Duration, max_ticks: u32) -> u32 {
+        self.accumulator += delta;
+        let mut num_ticks = 0;
+        while self.accumulator >= self.period && num_ticks < max_ticks {
+            self.accumulator -= self.period;
+            num_ticks += 1;
+        }
+        if num_ticks == max_ticks {
+            self.accumulator = Duration::ZERO;
+        }
+
+        num_ticks
+    }
+}
@@ -0,0 +1,168 @@
+use crate::{grid::DecayGrid, *};
+use bevy::utils::{StableHashMap, StableHashSet};
+use kd_tree::KdTree;
+
+/// Finds the cells holding a signal around a cell, keys are pheromone grid cells
+pub trait NeighborIndex: Send + Sync {
+    /// A signal was emitted on the cell
+    fn insert(&mut self, key: (i32, i32));
+    /// Periodic refresh from the current signals
+    fn update(&mut self, signals: &dyn DecayGrid);
+    /// Cells within `radius` of `key`, `None` until the index is built
+    fn within_radius(&self, key: (i32, i32), radius: f32) -> Option<Vec<(i32, i32)>>;
+}
+
+/// Rebuilt from scratch on every update, it doesn't see the signals emitted since
+#[derive(Default)]
+pub struct KdTreeIndex {
+    tree: Option<KdTree<[f32; 2]>>,
+}
+
+/// Uniform grid of buckets holding the cells with a signal.
+/// Cells are added as signals are emitted and dropped once they decay to zero
+pub struct BucketIndex {
+    bucket_size: i32,
+    buckets: StableHashMap<(i32, i32), StableHashSet<(i32, i32)>>,
+}
+
+impl NeighborIndexKind {
+    pub fn new_index(&self, config: &PheromoneConfig) -> Box<dyn NeighborIndex> {
+        match self {
+            NeighborIndexKind::KdTree => Box::<KdTreeIndex>::default(),
+            NeighborIndexKind::Buckets => Box::new(BucketIndex::new(config.bucket_size)),
+        }
+    }
+}
+
+impl NeighborIndex for KdTreeIndex {
+    fn insert(&mut self, _key: (i32, i32)) {}
+
+    fn update(&mut self, signals: &dyn DecayGrid) {
+        let mut pts = Vec::new();
+        for (k, v) in signals.iter_values() {
+            if v <= 0.0 {
+                continue;
+            }
+
+            let (x, y) = k;
+            pts.push([x as f32, y as f32]);
+        }
+
+        self.tree = Some(KdTree::build_by_ordered_float(pts));
+    }
+
+    fn within_radius(&self, key: (i32, i32), radius: f32) -> Option<Vec<(i32, i32)>> {
+        let tree = self.tree.as_ref()?;
+        let found = tree.within_radius(&[key.0 as f32, key.1 as f32], radius);
+
+        Some(found.iter().map(|[x, y]| (*x as i32, *y as i32)).collect())
+    }
+}
+
+impl BucketIndex {
+    pub fn new(bucket_size: i32) -> Self {
+        Self {
+            bucket_size: bucket_size.max(1),
+            buckets: StableHashMap::default(),
+        }
+    }
+
+    fn get_bucket(&self, key: (i32, i32)) -> (i32, i32) {
+        (
+            key.0.div_euclid(self.bucket_size),
+            key.1.div_euclid(self.bucket_size),
+        )
+    }
+}
+
+impl NeighborIndex for BucketIndex {
+    fn insert(&mut self, key: (i32, i32)) {
+        let bucket = self.get_bucket(key);
+        self.buckets.entry(bucket).or_default().insert(key);
+    }
+
+    fn update(&mut self, signals: &dyn DecayGrid) {
+        for bucket in self.buckets.values_mut() {
+            bucket.retain(|key| signals.get_value(key).is_some_and(|v| v > 0.0));
+        }
+        self.buckets.retain(|_, bucket| !bucket.is_empty());
+    }
+
+    fn within_radius(&self, key: (i32, i32), radius: f32) -> Option<Vec<(i32, i32)>> {
+        let r = radius.ceil() as i32;
+        let (min_x, min_y) = self.get_bucket((key.0 - r, key.1 - r));
+        let (max_x, max_y) = self.get_bucket((key.0 + r, key.1 + r));
+
+        let mut found = Vec::new();
+        for bx in min_x..=max_x {
+            for by in min_y..=max_y {
+                let Some(bucket) = self.buckets.get(&(bx, by)) else {
+                    continue;
+                };
+
+                found.extend(bucket.iter().filter(|(x, y)| {
+                    let (dx, dy) = ((x - key.0) as f32, (y - key.1) as f32);
+                    // Strictly within, like the kd tree
+                    dx * dx + dy * dy < radius * radius
+                }));
+            }
+        }
+
+        Some(found)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::SparseDecayGrid;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    fn sorted(mut keys: Vec<(i32, i32)>) -> Vec<(i32, i32)> {
+        keys.sort_unstable();
+        keys
+    }
+
+    #[test]
+    fn bucket_index_matches_kd_tree() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut signals = SparseDecayGrid::new(500.0);
+        let mut buckets = BucketIndex::new(16);
+        for _ in 0..2000 {
+            let key = (rng.gen_range(-100..100), rng.gen_range(-100..100));
+            signals.add_value(&key, 10.0, 1.0);
+            buckets.insert(key);
+        }
+        let mut kd_tree = KdTreeIndex::default();
+        kd_tree.update(&signals);
+        buckets.update(&signals);
+
+        for _ in 0..200 {
+            let key = (rng.gen_range(-120..120), rng.gen_range(-120..120));
+            for radius in [0.0, 1.0, 3.0, 7.5, 20.0, 50.0] {
+                assert_eq!(
+                    sorted(buckets.within_radius(key, radius).unwrap()),
+                    sorted(kd_tree.within_radius(key, radius).unwrap()),
+                    "cells within {radius} of {key:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn bucket_index_drops_decayed_cells() {
+        let mut signals = SparseDecayGrid::new(500.0);
+        let mut buckets = BucketIndex::new(4);
+        for key in [(0, 0), (1, 1), (10, 10)] {
+            signals.add_value(&key, 1.0, 1.0);
+            buckets.insert(key);
+        }
+        signals.remove_value(&(1, 1));
+        buckets.update(&signals);
+
+        assert_eq!(
+            sorted(buckets.within_radius((0, 0), 20.0).unwrap()),
+            vec![(0, 0), (10, 10)]
+        );
+    }
+}
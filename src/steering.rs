@@ -0,0 +1,166 @@
+use crate::{ant::AntTask, colony::Colony, grid::WorldGrid, pheromone::ColonyPheromones, *};
+use bevy::{prelude::*, utils::StableHashMap};
+use rand::{Rng, RngCore};
+use std::f32::consts::PI;
+
+pub const MIDPOINT_STEERING: &str = "midpoint";
+pub const SENSOR_STEERING: &str = "sensors";
+
+/// Steers ants that aren't close enough to their food or home to be pulled in.
+/// Register implementations with `App::add_steering_strategy`, the one
+/// named by `ants.steering` in the config is used
+pub trait SteeringStrategy: Send + Sync + 'static {
+    /// Acceleration added to the ant's velocity, `None` when there's nothing
+    /// to follow and the ant should keep exploring
+    fn steer(&self, ant: &SteeringInput, rng: &mut dyn RngCore) -> Option<Vec2>;
+}
+
+/// What a steering strategy knows about a single ant
+pub struct SteeringInput<'a> {
+    pub pos: Vec2,
+    pub velocity: Vec2,
+    pub task: AntTask,
+    pub colony: &'a Colony,
+    /// Pheromones of the ant's colony
+    pub pheromones: &'a ColonyPheromones,
+    pub scan_radius: f32,
+    pub config: &'a AntConfig,
+}
+
+/// Steering strategies by name
+#[derive(Resource, Default)]
+pub struct SteeringStrategies(StableHashMap<String, Box<dyn SteeringStrategy>>);
+
+pub trait AddSteeringStrategy {
+    /// Registers a steering strategy, replacing any strategy with the same name
+    fn add_steering_strategy(
+        &mut self,
+        name: impl Into<String>,
+        strategy: impl SteeringStrategy,
+    ) -> &mut Self;
+}
+
+/// Steers towards the weighted midpoint of the pheromones within the scan radius
+pub struct MidpointSteering;
+
+/// Steers towards the strongest of the left, forward and right sensors,
+/// forward wins ties so ants keep their heading on a uniform trail
+pub struct SensorSteering;
+
+impl AddSteeringStrategy for App {
+    fn add_steering_strategy(
+        &mut self,
+        name: impl Into<String>,
+        strategy: impl SteeringStrategy,
+    ) -> &mut Self {
+        self.init_resource::<SteeringStrategies>();
+        self.world
+            .resource_mut::<SteeringStrategies>()
+            .0
+            .insert(name.into(), Box::new(strategy));
+        self
+    }
+}
+
+impl SteeringStrategies {
+    /// The strategy named in the config, or midpoint steering if there's none by that name
+    pub fn get(&self, name: &str) -> Option<&dyn SteeringStrategy> {
+        self.0
+            .get(name)
+            .or_else(|| self.0.get(MIDPOINT_STEERING))
+            .map(|s| s.as_ref())
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.0.contains_key(name)
+    }
+
+    /// Registers the built in strategies, unless they're replaced already
+    pub(crate) fn add_builtin(&mut self) {
+        self.0
+            .entry(MIDPOINT_STEERING.to_string())
+            .or_insert_with(|| Box::new(MidpointSteering));
+        self.0
+            .entry(SENSOR_STEERING.to_string())
+            .or_insert_with(|| Box::new(SensorSteering));
+    }
+}
+
+impl SteeringInput<'_> {
+    /// Trail the ant follows for its current task
+    pub fn trail(&self) -> &WorldGrid {
+        match self.task {
+            AntTask::FindFood => self.pheromones.to_food(),
+            AntTask::FindHome | AntTask::ReturnToEat => self.pheromones.to_home(),
+        }
+    }
+
+    /// Acceleration towards `target`, with the random strength the built in strategies use
+    pub fn steer_towards(&self, target: Vec2, rng: &mut dyn RngCore) -> Vec2 {
+        get_steering_force(target, self.pos, self.velocity)
+            * rng.gen_range(0.4..=self.config.steering_force_factor.max(0.4))
+    }
+}
+
+impl SteeringStrategy for MidpointSteering {
+    fn steer(&self, ant: &SteeringInput, rng: &mut dyn RngCore) -> Option<Vec2> {
+        let target = ant
+            .trail()
+            .get_steer_target(&ant.pos.extend(0.0), ant.scan_radius)?;
+
+        Some(ant.steer_towards(target, rng))
+    }
+}
+
+impl SteeringStrategy for SensorSteering {
+    fn steer(&self, ant: &SteeringInput, rng: &mut dyn RngCore) -> Option<Vec2> {
+        let angle = ant.config.sensor_angle_deg.to_radians();
+        let forward = ant.velocity.normalize_or_zero() * ant.config.sensor_distance;
+        let sensors = [
+            forward,
+            Vec2::from_angle(angle).rotate(forward),
+            Vec2::from_angle(-angle).rotate(forward),
+        ];
+
+        let trail = ant.trail();
+        let (strength, target) = sensors
+            .iter()
+            .map(|sensor| (trail.sample_signal(ant.pos + *sensor), ant.pos + *sensor))
+            .reduce(|best, s| if s.0 > best.0 { s } else { best })?;
+        if strength <= 0.0 {
+            return None;
+        }
+
+        Some(ant.steer_towards(target, rng))
+    }
+}
+
+pub fn get_steering_force(target: Vec2, current: Vec2, velocity: Vec2) -> Vec2 {
+    let desired = target - current;
+    let steering = desired - velocity;
+    steering * 0.05
+}
+
+/// Turns away from a signal, towards the weaker of two probes `look_ahead`
+/// ahead on the left and right. Zero when there's no signal on either side
+pub fn get_avoidance_force(
+    pos: Vec2,
+    velocity: Vec2,
+    look_ahead: f32,
+    signal: impl Fn(Vec2) -> f32,
+) -> Vec2 {
+    let ahead = velocity.normalize_or_zero() * look_ahead;
+    let left = Vec2::from_angle(PI / 4.0).rotate(ahead);
+    let right = Vec2::from_angle(-PI / 4.0).rotate(ahead);
+    let (left_signal, right_signal) = (signal(pos + left), signal(pos + right));
+    if left_signal + right_signal <= 0.0 {
+        return Vec2::ZERO;
+    }
+
+    let away = if left_signal < right_signal {
+        left
+    } else {
+        right
+    };
+    get_steering_force(pos + away, pos, velocity)
+}
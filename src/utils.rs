@@ -3,7 +3,7 @@ use bevy::{
     math::{vec2, vec3},
     prelude::*,
 };
-use rand::{thread_rng, Rng};
+use rand::Rng;
 use std::f32::consts::PI;
 
 // Function to find the n points with max z values
@@ -64,17 +64,18 @@ pub fn rotate_vector(vector: &Vec2, angle_deg: f32) -> Vec2 {
     vec2(x, y)
 }
 
-pub fn window_to_grid(x: i32, y: i32) -> (i32, i32) {
+pub fn world_to_grid(x: i32, y: i32, grid_size: usize, world: &WorldConfig) -> (i32, i32) {
     // Convert from center to top left co-ords
-    let (tx, ty) = (x + (W as usize / 2) as i32, (H as usize / 2) as i32 - y);
-    let (tx, ty) = (tx / PH_UNIT_GRID_SIZE as i32, ty / PH_UNIT_GRID_SIZE as i32);
+    let (tx, ty) = (x + world.width as i32 / 2, world.height as i32 / 2 - y);
+    let (tx, ty) = (tx / grid_size as i32, ty / grid_size as i32);
 
     (tx, ty)
 }
 
-pub fn grid_to_window(tx: i32, ty: i32) -> (i32, i32) {
-    let x = tx * PH_UNIT_GRID_SIZE as i32 + PH_UNIT_GRID_SIZE as i32 / 2 - (W as i32 / 2);
-    let y = (H as i32 / 2) - ty * PH_UNIT_GRID_SIZE as i32 - PH_UNIT_GRID_SIZE as i32 / 2;
+pub fn grid_to_world(tx: i32, ty: i32, grid_size: usize, world: &WorldConfig) -> (i32, i32) {
+    let grid_size = grid_size as i32;
+    let x = tx * grid_size + grid_size / 2 - world.width as i32 / 2;
+    let y = world.height as i32 / 2 - ty * grid_size - grid_size / 2;
 
     (x, y)
 }
@@ -91,21 +92,27 @@ pub fn vector_to_angle_deg(vec: Vec2) -> f32 {
     }
 }
 
-pub fn get_rand_unit_vec3() -> Vec3 {
-    let mut rng = thread_rng();
+pub fn get_rand_unit_vec3(rng: &mut impl Rng) -> Vec3 {
     vec3(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 0.0).normalize()
 }
 
-pub fn get_rand_vec2() -> Vec2 {
-    let mut rng = thread_rng();
-    vec2(rng.gen_range(-W..W), rng.gen_range(-H..H))
+pub fn get_rand_vec2(rng: &mut impl Rng, world: &WorldConfig) -> Vec2 {
+    let (w, h) = (world.width / 2.0, world.height / 2.0);
+    vec2(rng.gen_range(-w..w), rng.gen_range(-h..h))
 }
 
-pub fn get_rand_unit_vec2() -> Vec2 {
-    let rand_vec3 = get_rand_unit_vec3();
+pub fn get_rand_unit_vec2(rng: &mut impl Rng) -> Vec2 {
+    let rand_vec3 = get_rand_unit_vec3(rng);
     vec2(rand_vec3.x, rand_vec3.y)
 }
 
+/// Standard normal sample, using the Box-Muller transform
+pub fn get_rand_normal(rng: &mut impl Rng) -> f32 {
+    let u1: f32 = 1.0 - rng.gen::<f32>();
+    let u2: f32 = rng.gen();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos()
+}
+
 // Function to partition the array based on the pivot (max z value)
 fn partition(points: &mut [(i32, i32, f32)], low: usize, high: usize) -> usize {
     let pivot = points[high].2;
@@ -127,11 +134,9 @@ fn quickselect(points: &mut [(i32, i32, f32)], low: usize, high: usize, n: usize
     if low < high {
         let pivot_index = partition(points, low, high);
 
-        if pivot_index == n - 1 {
-            return;
-        } else if pivot_index > n - 1 {
+        if pivot_index > n - 1 {
             quickselect(points, low, pivot_index - 1, n);
-        } else {
+        } else if pivot_index < n - 1 {
             quickselect(points, pivot_index + 1, high, n);
         }
     }